@@ -0,0 +1,122 @@
+use std::io::{Read, Write};
+
+use crate::error::Result;
+
+/// Common interface of all encodings, allowing them to be used on their own.
+///
+/// The default streaming variants read the whole input into memory and pass it to
+/// `encode` or `decode`, so their memory use grows with the input. Encodings which
+/// can work incrementally override them to run in bounded memory.
+pub trait Codec {
+    /// Encodes the given data
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>>;
+
+    /// Restores data produced by `encode`
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>>;
+
+    /// Encodes everything from reader into writer, returns the number of bytes written.
+    ///
+    /// Unless overridden, the whole input and output are buffered.
+    fn encode_stream(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<u64> {
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer)?;
+        write_all(writer, &self.encode(&buffer)?)
+    }
+
+    /// Decodes everything from reader into writer, returns the number of bytes written.
+    ///
+    /// Unless overridden, the whole input and output are buffered.
+    fn decode_stream(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<u64> {
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer)?;
+        write_all(writer, &self.decode(&buffer)?)
+    }
+}
+
+fn write_all(writer: &mut dyn Write, data: &[u8]) -> Result<u64> {
+    writer.write_all(data)?;
+    writer.flush()?;
+    Ok(data.len() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::encodings::arithmetic_coder::ArithmeticCoder;
+    use crate::encodings::bwt::Bwt;
+    use crate::encodings::bzip2::Bzip2;
+    use crate::encodings::deflate::gzip::Gzip;
+    use crate::encodings::deflate::Deflate;
+    use crate::encodings::huffman::Huffman;
+    use crate::encodings::lipt::Lipt;
+    use crate::encodings::lz77::Lz77;
+    use crate::encodings::mtf::variants::{MtfVariant, Variant};
+    use crate::encodings::mtf::Mtf;
+    use crate::encodings::ppm::Ppm;
+    use crate::encodings::rans::{self, Rans};
+    use crate::encodings::rle::Rle;
+    use crate::encodings::startransform::StarTransform;
+    use crate::encodings::stream::Comprs;
+    use crate::encodings::text::TextFilter;
+    use crate::error::Result;
+
+    use super::Codec;
+
+    #[test]
+    fn test_codecs() -> Result<()> {
+        let data = String::from(
+            "There are many variations of passages of Lorem Ipsum available, \
+             but the majority have suffered alteration in some form, by injected humour, \
+             or randomised words which don't look even slightly believable.",
+        )
+        .into_bytes();
+
+        let codecs: Vec<Box<dyn Codec>> = vec![
+            Box::new(ArithmeticCoder),
+            Box::new(Bwt::default()),
+            Box::new(Bzip2::default()),
+            Box::new(Deflate::default()),
+            Box::new(Gzip::default()),
+            Box::new(Huffman),
+            Box::new(Lipt::default()),
+            Box::new(Lz77::default()),
+            Box::new(Mtf),
+            Box::new(MtfVariant::default()),
+            Box::new(MtfVariant {
+                variant: Variant::Mtf2,
+            }),
+            Box::new(MtfVariant {
+                variant: Variant::Sticky { threshold: 4 },
+            }),
+            Box::new(MtfVariant {
+                variant: Variant::Wfc { window: 256 },
+            }),
+            Box::new(Ppm::default()),
+            Box::new(Rans::default()),
+            Box::new(Rans {
+                mode: rans::Mode::Adaptive,
+            }),
+            Box::new(Rle),
+            Box::new(StarTransform),
+            Box::new(TextFilter),
+            Box::new(Comprs::default()),
+        ];
+
+        for codec in codecs {
+            for data in &[&data[..], b"", b"x", b"aa", &[0xff; 3]] {
+                let encoded = codec.encode(data)?;
+                assert_eq!(codec.decode(&encoded)?, *data);
+
+                let mut streamed = Vec::new();
+                let len = codec.encode_stream(&mut &data[..], &mut streamed)?;
+                assert_eq!(len as usize, streamed.len());
+                assert_eq!(streamed, encoded);
+
+                let mut restored = Vec::new();
+                let len = codec.decode_stream(&mut streamed.as_slice(), &mut restored)?;
+                assert_eq!(len as usize, data.len());
+                assert_eq!(restored, *data);
+            }
+        }
+        Ok(())
+    }
+}
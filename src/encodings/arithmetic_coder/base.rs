@@ -1,4 +1,4 @@
-use std::io::Result;
+use std::io::{Error, ErrorKind, Result};
 
 use crate::encodings::arithmetic_coder::Symbol;
 
@@ -18,9 +18,15 @@ pub trait ArithmeticCoderBase {
     fn maximum_total(&self) -> usize;
 
     fn shift(&mut self) -> Result<()>;
-    fn underflow(&mut self);
+    fn underflow(&mut self) -> Result<()>;
 
     fn update<T: FrequencyTable>(&mut self, freqtable: &mut T, symbol: Symbol) -> Result<()> {
+        let (symlow, symhigh) = freqtable.interval(symbol);
+        self.narrow(symlow, symhigh, freqtable.total())
+    }
+
+    /// Narrows the range to the interval [symlow, symhigh) out of `total`
+    fn narrow(&mut self, symlow: usize, symhigh: usize, total: usize) -> Result<()> {
         let (low, high) = (self.low(), self.high());
         debug_assert!(low < high, "low or high out of range");
 
@@ -31,14 +37,13 @@ pub trait ArithmeticCoderBase {
         debug_assert!(self.minimum_range() <= range);
         debug_assert!(range <= self.full_range());
 
-        let symlow = freqtable.get_low(symbol);
-        let symhigh = freqtable.get_high(symbol);
-        let total = freqtable.total();
         debug_assert!(symlow != symhigh, "symbol has zero frequency");
-        debug_assert!(
-            total <= self.maximum_total(),
-            "cannot code symbol because total is too large"
-        );
+        if total > self.maximum_total() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "frequency total exceeds the coder limit",
+            ));
+        }
 
         let (mut low, mut high) = (
             low + symlow * range / total,
@@ -60,7 +65,7 @@ pub trait ArithmeticCoderBase {
         // While low's top two bits are 01 and high's are 10, delete the second highest bit of both
         let quarter_range = self.quarter_range();
         while (low & !high & quarter_range) != 0 {
-            self.underflow();
+            self.underflow()?;
             low = (low << 1) ^ half_range;
             high = ((high ^ half_range) << 1) | half_range | 1;
         }
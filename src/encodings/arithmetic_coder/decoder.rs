@@ -1,12 +1,13 @@
 use std::cmp;
-use std::io::{Read, Result};
+use std::io::{Error, ErrorKind, Read, Result};
 
 use bitbit::{BitReader, MSB};
 
 use crate::encodings::arithmetic_coder::Symbol;
+use crate::error::ComprsError;
 
 use super::base::ArithmeticCoderBase;
-use super::FrequencyTable;
+use super::{EntropyDecoder, FrequencyTable};
 
 pub struct ArithmeticDecoder<R: Read> {
     reader: BitReader<R, MSB>,
@@ -23,6 +24,19 @@ pub struct ArithmeticDecoder<R: Read> {
     quarter_range: usize,
     minimum_range: usize,
     maximum_total: usize,
+    /// Zero bits which may still be read past the end of the input
+    padding: usize,
+}
+
+impl<R: Read> EntropyDecoder for ArithmeticDecoder<R> {
+    #[inline]
+    fn read<T: FrequencyTable>(&mut self, freqtable: &mut T) -> Result<Symbol> {
+        ArithmeticDecoder::read(self, freqtable)
+    }
+
+    fn check_end(&self) -> Result<()> {
+        ArithmeticDecoder::check_end(self)
+    }
 }
 
 impl<R: Read> ArithmeticCoderBase for ArithmeticDecoder<R> {
@@ -66,28 +80,21 @@ impl<R: Read> ArithmeticCoderBase for ArithmeticDecoder<R> {
     }
 
     fn shift(&mut self) -> Result<()> {
-        let bit = if self.reader.read_bit().unwrap_or(false) {
-            1
-        } else {
-            0
-        };
+        let bit = self.read_bit()?;
         self.code = ((self.code << 1) & self.state_mask) | bit;
         Ok(())
     }
 
-    fn underflow(&mut self) {
-        let bit = if self.reader.read_bit().unwrap_or(false) {
-            1
-        } else {
-            0
-        };
+    fn underflow(&mut self) -> Result<()> {
+        let bit = self.read_bit()?;
         self.code =
             (self.code & self.half_range) | ((self.code << 1) & (self.state_mask >> 1)) | bit;
+        Ok(())
     }
 }
 
 impl<R: Read> ArithmeticDecoder<R> {
-    pub fn new(mut reader: BitReader<R, MSB>, num_bits: usize) -> Result<Self> {
+    pub fn new(reader: BitReader<R, MSB>, num_bits: usize) -> Result<Self> {
         let num_state_bits = num_bits;
         let full_range = 1 << num_state_bits;
         // The top bit at width num_state_bits, which is 0100...000.
@@ -98,7 +105,7 @@ impl<R: Read> ArithmeticDecoder<R> {
         let minimum_range = quarter_range + 2; // At least 2
                                                // Maximum allowed total from a frequency table at all times during coding. This differs from Java
                                                // and C++ because Python's native bigint avoids constraining the size of intermediate computations.
-        let maximum_total = cmp::min(std::usize::MAX / full_range, minimum_range);
+        let maximum_total = cmp::min(usize::MAX / full_range, minimum_range);
         // Bit mask of num_state_bits ones, which is 0111...111.
         let state_mask = full_range - 1;
 
@@ -107,13 +114,7 @@ impl<R: Read> ArithmeticDecoder<R> {
         // High end of this arithmetic coder's current range. Conceptually has an infinite number of trailing 1s.
         let high = state_mask;
 
-        let mut code = 0;
-        for _ in 0..num_bits {
-            let bit = if reader.read_bit()? { 1 } else { 0 };
-            code = code << 1 | bit;
-        }
-
-        Ok(ArithmeticDecoder {
+        let mut decoder = ArithmeticDecoder {
             reader,
             low,
             high,
@@ -123,17 +124,54 @@ impl<R: Read> ArithmeticDecoder<R> {
             quarter_range,
             minimum_range,
             maximum_total,
-            code,
-        })
+            code: 0,
+            padding: num_bits,
+        };
+        // The initial state is padded like any later bit, so payloads shorter than a state decode
+        for _ in 0..num_bits {
+            decoder.code = decoder.code << 1 | decoder.read_bit()?;
+        }
+        Ok(decoder)
+    }
+
+    /// Checks that the code is a one bit followed by zeros, as written by the encoder's `finish`.
+    /// Trailing bits below the top bits do not change the decoded symbols otherwise.
+    pub fn check_end(&self) -> Result<()> {
+        if self.code != self.half_range {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                ComprsError::corrupt("arithmetic coded data has a bad end"),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Reads the next code bit, the input is padded with zeros for at most a full state,
+    /// which is more than the encoder leaves out
+    fn read_bit(&mut self) -> Result<usize> {
+        match self.reader.read_bit() {
+            Ok(bit) => Ok(bit as usize),
+            Err(ref e) if e.kind() == ErrorKind::UnexpectedEof && self.padding > 0 => {
+                self.padding -= 1;
+                Ok(0)
+            }
+            Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                ComprsError::corrupt("arithmetic coded data ended early"),
+            )),
+            Err(e) => Err(e),
+        }
     }
 
     pub fn read<T: FrequencyTable>(&mut self, freqtable: &mut T) -> Result<Symbol> {
         let total = freqtable.total();
 
-        debug_assert!(
-            total <= self.maximum_total,
-            "cannot decode symbol because total is too large"
-        );
+        if total > self.maximum_total {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "frequency total exceeds the coder limit",
+            ));
+        }
 
         let range = self.high - self.low + 1;
         let offset = self.code - self.low;
@@ -141,23 +179,10 @@ impl<R: Read> ArithmeticDecoder<R> {
         debug_assert!(value * range / total <= offset);
         debug_assert!(value < total);
 
-        // A kind of binary search. Find highest symbol such that freqs.get_low(symbol) <= value.
-        let mut start = 0;
-        let mut end = freqtable.get_symbol_limit();
-        while end - start > 1 {
-            let middle = (start + end) >> 1;
-            if freqtable.get_low(middle) > value {
-                end = middle;
-            } else {
-                start = middle;
-            }
-        }
-        debug_assert_eq!(start + 1, end);
-
-        let symbol = start;
-        debug_assert!(freqtable.get_low(symbol) * range / total <= offset);
-        debug_assert!(offset < freqtable.get_high(symbol) * range / total);
-        self.update(freqtable, symbol)?;
+        let (symbol, symlow, symhigh) = freqtable.find_interval(value);
+        debug_assert!(symlow * range / total <= offset);
+        debug_assert!(offset < symhigh * range / total);
+        self.narrow(symlow, symhigh, total)?;
 
         debug_assert!(self.low <= self.code, "code out of range");
         debug_assert!(self.code <= self.high, "code out of range");
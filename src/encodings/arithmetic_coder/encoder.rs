@@ -6,7 +6,7 @@ use bitbit::BitWriter;
 use crate::encodings::arithmetic_coder::Symbol;
 
 use super::base::ArithmeticCoderBase;
-use super::FrequencyTable;
+use super::{EntropyEncoder, FrequencyTable};
 
 pub struct ArithmeticEncoder<W: Write> {
     writer: BitWriter<W>,
@@ -37,7 +37,7 @@ impl<W: Write> ArithmeticEncoder<W> {
         let minimum_range = quarter_range + 2; // At least 2
                                                // Maximum allowed total from a frequency table at all times during coding. This differs from Java
                                                // and C++ because Python's native bigint avoids constraining the size of intermediate computations.
-        let maximum_total = cmp::min(std::usize::MAX / full_range, minimum_range);
+        let maximum_total = cmp::min(usize::MAX / full_range, minimum_range);
         // Bit mask of num_state_bits ones, which is 0111...111.
         let state_mask = full_range - 1;
 
@@ -70,8 +70,14 @@ impl<W: Write> ArithmeticEncoder<W> {
     /// Terminates the arithmetic coding by flushing any buffered bits, so that the output can be decoded properly.
     /// It is important that this method must be called at the end of the each encoding process.
     /// Note that this method merely writes data to the underlying output stream but does not close it.
+    /// The saved underflow bits are written too, so that the decoder never pads more
+    /// than its state with zeros.
     pub fn finish(&mut self) -> Result<()> {
         self.writer.write_bit(true)?;
+        for _ in 0..self.num_underflow {
+            self.writer.write_bit(false)?;
+        }
+        self.num_underflow = 0;
         self.writer.write_byte(0)
     }
 
@@ -82,6 +88,13 @@ impl<W: Write> ArithmeticEncoder<W> {
     }
 }
 
+impl<W: Write> EntropyEncoder for ArithmeticEncoder<W> {
+    #[inline]
+    fn write<T: FrequencyTable>(&mut self, freqtable: &mut T, symbol: Symbol) -> Result<()> {
+        ArithmeticEncoder::write(self, freqtable, symbol)
+    }
+}
+
 impl<W: Write> ArithmeticCoderBase for ArithmeticEncoder<W> {
     fn set_low(&mut self, value: usize) {
         self.low = value
@@ -138,7 +151,8 @@ impl<W: Write> ArithmeticCoderBase for ArithmeticEncoder<W> {
         Ok(())
     }
 
-    fn underflow(&mut self) {
+    fn underflow(&mut self) -> Result<()> {
         self.num_underflow += 1;
+        Ok(())
     }
 }
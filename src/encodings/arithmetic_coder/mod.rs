@@ -1,20 +1,184 @@
+use std::cmp;
 use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+use bitbit::{BitReader, BitWriter};
+
+use crate::codec::Codec;
+use crate::error::{ComprsError, Result};
+
+use self::decoder::ArithmeticDecoder;
+use self::encoder::ArithmeticEncoder;
 
 mod base;
 pub mod decoder;
 pub mod encoder;
+pub mod range;
 
 pub type Symbol = u16;
 
+const NUM_BITS: usize = 32;
+/// Total at which the order-0 frequencies are halved
+const RESCALE_LIMIT: usize = 1 << 16;
+
+/// Adaptive order-0 arithmetic coder for bytes
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ArithmeticCoder;
+
+impl ArithmeticCoder {
+    fn initial_frequencies() -> FenwickFrequencyTable {
+        let mut freqs = FenwickFrequencyTable::new(256);
+        for symbol in 0..256 {
+            freqs.set(symbol, 1);
+        }
+        freqs
+    }
+}
+
+impl Codec for ArithmeticCoder {
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut buffer = Vec::with_capacity(data.len() / 2 + 8);
+        buffer.extend_from_slice(&(data.len() as u64).to_le_bytes());
+
+        let mut encoder = ArithmeticEncoder::new(BitWriter::new(buffer), NUM_BITS);
+        let mut freqs = ArithmeticCoder::initial_frequencies();
+        for byte in data {
+            encoder.write(&mut freqs, Symbol::from(*byte))?;
+            freqs.increment_limited(Symbol::from(*byte), RESCALE_LIMIT);
+        }
+        encoder.finish()?;
+        Ok(encoder.inner_ref().clone())
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        if data.len() < 8 {
+            return Err(ComprsError::corrupt("truncated arithmetic coded data"));
+        }
+        let mut len = [0u8; 8];
+        len.copy_from_slice(&data[..8]);
+        let len = u64::from_le_bytes(len);
+
+        let mut decoder = ArithmeticDecoder::new(BitReader::new(&data[8..]), NUM_BITS)
+            .map_err(|_| ComprsError::corrupt("truncated arithmetic coded data"))?;
+        let mut freqs = ArithmeticCoder::initial_frequencies();
+        let mut decoded = Vec::with_capacity(data.len());
+        for _ in 0..len {
+            let symbol = decoder.read(&mut freqs)?;
+            decoded.push(symbol as u8);
+            freqs.increment_limited(symbol, RESCALE_LIMIT);
+        }
+        decoder.check_end()?;
+        Ok(decoded)
+    }
+}
+
+/// Entropy coder used to write the symbols of a model
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Backend {
+    /// Bitwise arithmetic coder
+    Arithmetic,
+    /// Bytewise range coder, faster at a negligible loss of ratio
+    Range,
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let printable = match *self {
+            Backend::Arithmetic => "arithmetic",
+            Backend::Range => "range",
+        };
+        write!(f, "{}", printable)
+    }
+}
+
+impl FromStr for Backend {
+    type Err = ComprsError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "arithmetic" => Ok(Backend::Arithmetic),
+            "range" => Ok(Backend::Range),
+            _ => Err(ComprsError::InvalidTransform(format!(
+                "unknown coder '{}'",
+                s
+            ))),
+        }
+    }
+}
+
+/// Writes symbols with the probabilities given by a frequency table
+pub trait EntropyEncoder {
+    fn write<T: FrequencyTable>(&mut self, freqtable: &mut T, symbol: Symbol) -> io::Result<()>;
+}
+
+/// Reads symbols written by the matching `EntropyEncoder`
+pub trait EntropyDecoder {
+    fn read<T: FrequencyTable>(&mut self, freqtable: &mut T) -> io::Result<Symbol>;
+
+    /// Checks that the state after the last symbol is the one written by the encoder's `finish`
+    fn check_end(&self) -> io::Result<()>;
+}
+
+/// Read-only access to the frequencies of symbols and their cumulative sums
 pub trait FrequencyTable {
-    fn new(num_symbols: u16) -> Self;
     fn get(&self, symbol: Symbol) -> usize;
     fn get_low(&self, symbol: Symbol) -> usize;
     fn get_high(&self, symbol: Symbol) -> usize;
     fn get_symbol_limit(&self) -> u16;
+    fn total(&self) -> usize;
+
+    /// Returns the highest symbol whose low cumulative frequency is not greater than `value`
+    fn find(&self, value: usize) -> Symbol {
+        let mut start = 0;
+        let mut end = self.get_symbol_limit();
+        while end - start > 1 {
+            let middle = (start + end) >> 1;
+            if self.get_low(middle) > value {
+                end = middle;
+            } else {
+                start = middle;
+            }
+        }
+        start
+    }
+
+    /// Returns the low and high cumulative frequency of `symbol`
+    fn interval(&self, symbol: Symbol) -> (usize, usize) {
+        (self.get_low(symbol), self.get_high(symbol))
+    }
+
+    /// Returns the symbol found for `value` along with its interval
+    fn find_interval(&self, value: usize) -> (Symbol, usize, usize) {
+        let symbol = self.find(value);
+        let (low, high) = self.interval(symbol);
+        (symbol, low, high)
+    }
+}
+
+/// Frequency table which is created empty and updated by the model
+pub trait FrequencyTableMut: FrequencyTable {
+    fn new(num_symbols: u16) -> Self;
     fn set(&mut self, symbol: Symbol, frequency: usize);
     fn increment(&mut self, symbol: Symbol);
-    fn total(&self) -> usize;
+
+    /// Halves all frequencies, rounding up so that symbols which occurred remain codable
+    fn halve(&mut self) {
+        for symbol in 0..self.get_symbol_limit() {
+            let frequency = self.get(symbol);
+            if frequency > 1 {
+                self.set(symbol, frequency.div_ceil(2));
+            }
+        }
+    }
+
+    /// Increments the frequency of `symbol` and halves all frequencies once the total exceeds `limit`
+    fn increment_limited(&mut self, symbol: Symbol, limit: usize) {
+        self.increment(symbol);
+        if self.total() > limit {
+            self.halve();
+        }
+    }
 }
 
 pub struct SimpleFrequencyTable {
@@ -29,14 +193,6 @@ impl SimpleFrequencyTable {
 }
 
 impl FrequencyTable for SimpleFrequencyTable {
-    #[inline]
-    fn new(num_symbols: Symbol) -> Self {
-        SimpleFrequencyTable {
-            frequencies: vec![0; num_symbols as usize],
-            total: 0,
-        }
-    }
-
     #[inline]
     fn get(&self, symbol: Symbol) -> usize {
         self.frequencies[symbol as usize]
@@ -57,6 +213,21 @@ impl FrequencyTable for SimpleFrequencyTable {
         self.frequencies.len() as Symbol
     }
 
+    #[inline]
+    fn total(&self) -> usize {
+        self.total
+    }
+}
+
+impl FrequencyTableMut for SimpleFrequencyTable {
+    #[inline]
+    fn new(num_symbols: Symbol) -> Self {
+        SimpleFrequencyTable {
+            frequencies: vec![0; num_symbols as usize],
+            total: 0,
+        }
+    }
+
     fn set(&mut self, symbol: Symbol, frequency: usize) {
         self.total -= self.frequencies[symbol as usize];
         self.frequencies[symbol as usize] = frequency;
@@ -67,30 +238,237 @@ impl FrequencyTable for SimpleFrequencyTable {
         self.total += 1;
         self.frequencies[symbol as usize] += 1;
     }
+}
+
+impl fmt::Debug for SimpleFrequencyTable {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("SimpleFrequencyTable")
+            .field("frequencies", &self.frequencies)
+            .finish()
+    }
+}
+
+/// Frequency table backed by a Fenwick tree, with O(log n) cumulative lookups and updates
+pub struct FenwickFrequencyTable {
+    // One-based binary indexed tree, tree[i] holds the sum of the range (i - lsb(i), i]
+    tree: Vec<u32>,
+    total: usize,
+}
+
+impl FenwickFrequencyTable {
+    /// Returns the sum of the frequencies of all symbols below `symbol`
+    fn cumulative(&self, symbol: Symbol) -> usize {
+        let mut sum = 0;
+        let mut i = symbol as usize;
+        while i > 0 {
+            sum += self.tree[i] as usize;
+            i &= i - 1;
+        }
+        sum
+    }
+
+    fn add(&mut self, symbol: Symbol, delta: i64) {
+        let mut i = symbol as usize + 1;
+        while i < self.tree.len() {
+            self.tree[i] = (i64::from(self.tree[i]) + delta) as u32;
+            i += i & i.wrapping_neg();
+        }
+        self.total = (self.total as i64 + delta) as usize;
+    }
+}
+
+impl FrequencyTable for FenwickFrequencyTable {
+    #[inline]
+    fn get(&self, symbol: Symbol) -> usize {
+        self.cumulative(symbol + 1) - self.cumulative(symbol)
+    }
+
+    #[inline]
+    fn get_low(&self, symbol: Symbol) -> usize {
+        self.cumulative(symbol)
+    }
+
+    #[inline]
+    fn get_high(&self, symbol: Symbol) -> usize {
+        self.cumulative(symbol + 1)
+    }
+
+    #[inline]
+    fn get_symbol_limit(&self) -> Symbol {
+        (self.tree.len() - 1) as Symbol
+    }
 
     #[inline]
     fn total(&self) -> usize {
         self.total
     }
+
+    /// Descends the tree instead of searching over cumulative lookups
+    fn find(&self, value: usize) -> Symbol {
+        let len = self.tree.len();
+        let mut pos = 0;
+        let mut remaining = value;
+        let mut step = (len - 1).next_power_of_two();
+        while step > 0 {
+            let next = pos + step;
+            if next < len && (self.tree[next] as usize) <= remaining {
+                pos = next;
+                remaining -= self.tree[next] as usize;
+            }
+            step >>= 1;
+        }
+        // pos symbols have a cumulative frequency not greater than value
+        cmp::min(pos, len - 2) as Symbol
+    }
 }
 
-impl fmt::Debug for SimpleFrequencyTable {
+impl FrequencyTableMut for FenwickFrequencyTable {
+    fn new(num_symbols: Symbol) -> Self {
+        FenwickFrequencyTable {
+            tree: vec![0; num_symbols as usize + 1],
+            total: 0,
+        }
+    }
+
+    fn set(&mut self, symbol: Symbol, frequency: usize) {
+        let delta = frequency as i64 - self.get(symbol) as i64;
+        self.add(symbol, delta);
+    }
+
+    #[inline]
+    fn increment(&mut self, symbol: Symbol) {
+        self.add(symbol, 1);
+    }
+}
+
+impl fmt::Debug for FenwickFrequencyTable {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        fmt.debug_struct("SimpleFrequencyTable")
-            .field("frequencies", &self.frequencies)
+        let frequencies: Vec<usize> = (0..self.get_symbol_limit()).map(|s| self.get(s)).collect();
+        fmt.debug_struct("FenwickFrequencyTable")
+            .field("frequencies", &frequencies)
             .finish()
     }
 }
 
+/// Set of symbols, used to exclude symbols from a frequency table
+#[derive(Clone, Debug)]
+pub struct SymbolSet {
+    mask: Vec<u64>,
+    symbols: Vec<Symbol>,
+}
+
+impl SymbolSet {
+    pub fn new(num_symbols: Symbol) -> Self {
+        SymbolSet {
+            mask: vec![0; (num_symbols as usize).div_ceil(64)],
+            symbols: Vec::with_capacity(num_symbols as usize),
+        }
+    }
+
+    #[inline]
+    pub fn contains(&self, symbol: Symbol) -> bool {
+        self.mask[symbol as usize / 64] & (1 << (symbol % 64)) != 0
+    }
+
+    pub fn insert(&mut self, symbol: Symbol) {
+        if !self.contains(symbol) {
+            self.mask[symbol as usize / 64] |= 1 << (symbol % 64);
+            self.symbols.push(symbol);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for word in self.mask.iter_mut() {
+            *word = 0;
+        }
+        self.symbols.clear();
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+}
+
+/// Read-only view of a frequency table in which the excluded symbols have zero frequency
+pub struct MaskedFrequencyTable<'a, T: FrequencyTable> {
+    inner: &'a T,
+    excluded: &'a SymbolSet,
+    // Excluded symbols in ascending order along with the sum of their frequencies up to and including them
+    cumulative: Vec<(Symbol, usize)>,
+}
+
+impl<'a, T: FrequencyTable> MaskedFrequencyTable<'a, T> {
+    pub fn new(inner: &'a T, excluded: &'a SymbolSet) -> Self {
+        let mut symbols = excluded.symbols.clone();
+        symbols.sort_unstable();
+        let mut sum = 0;
+        let cumulative = symbols
+            .into_iter()
+            .map(|symbol| {
+                sum += inner.get(symbol);
+                (symbol, sum)
+            })
+            .collect();
+        MaskedFrequencyTable {
+            inner,
+            excluded,
+            cumulative,
+        }
+    }
+
+    /// Returns the sum of the excluded frequencies below `symbol`
+    fn excluded_below(&self, symbol: Symbol) -> usize {
+        match self.cumulative.partition_point(|(s, _)| *s < symbol) {
+            0 => 0,
+            i => self.cumulative[i - 1].1,
+        }
+    }
+}
+
+impl<'a, T: FrequencyTable> FrequencyTable for MaskedFrequencyTable<'a, T> {
+    #[inline]
+    fn get(&self, symbol: Symbol) -> usize {
+        if self.excluded.contains(symbol) {
+            0
+        } else {
+            self.inner.get(symbol)
+        }
+    }
+
+    #[inline]
+    fn get_low(&self, symbol: Symbol) -> usize {
+        self.inner.get_low(symbol) - self.excluded_below(symbol)
+    }
+
+    #[inline]
+    fn get_high(&self, symbol: Symbol) -> usize {
+        self.inner.get_high(symbol) - self.excluded_below(symbol + 1)
+    }
+
+    #[inline]
+    fn get_symbol_limit(&self) -> Symbol {
+        self.inner.get_symbol_limit()
+    }
+
+    #[inline]
+    fn total(&self) -> usize {
+        self.inner.total() - self.cumulative.last().map_or(0, |(_, sum)| *sum)
+    }
+}
+
+/// Table in which all symbols have a frequency of one
 pub struct FlatFrequencyTable {
     num_symbols: Symbol,
 }
 
-impl FrequencyTable for FlatFrequencyTable {
-    fn new(num_symbols: Symbol) -> Self {
+impl FlatFrequencyTable {
+    pub fn new(num_symbols: Symbol) -> Self {
         FlatFrequencyTable { num_symbols }
     }
+}
 
+impl FrequencyTable for FlatFrequencyTable {
     #[inline]
     fn get(&self, _symbol: Symbol) -> usize {
         1
@@ -111,16 +489,162 @@ impl FrequencyTable for FlatFrequencyTable {
         self.num_symbols
     }
 
-    fn set(&mut self, _symbol: Symbol, _frequency: usize) {
-        unimplemented!()
+    #[inline]
+    fn total(&self) -> usize {
+        self.num_symbols as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codec::Codec;
+
+    use super::range::{RangeDecoder, RangeEncoder};
+    use super::{
+        ArithmeticCoder, FenwickFrequencyTable, FlatFrequencyTable, FrequencyTable,
+        FrequencyTableMut, MaskedFrequencyTable, SimpleFrequencyTable, SymbolSet,
+    };
+
+    #[test]
+    fn test_codec() {
+        let data = "abracadabra, ".repeat(50).into_bytes();
+        let encoded = ArithmeticCoder.encode(&data).unwrap();
+        assert!(encoded.len() < data.len());
+        assert_eq!(ArithmeticCoder.decode(&encoded).unwrap(), data);
+        assert!(ArithmeticCoder.decode(&encoded[..4]).is_err());
+
+        // Symbols past the end of the data are not decoded from padding
+        let mut longer = encoded.clone();
+        longer[..8].copy_from_slice(&(data.len() as u64 + 100).to_le_bytes());
+        assert!(ArithmeticCoder.decode(&longer).is_err());
+
+        // Flipped trailing bits do not go unnoticed, even where they leave the symbols intact
+        for bit in 0..32 {
+            let mut trailing = encoded.clone();
+            let i = trailing.len() - 1 - bit / 8;
+            trailing[i] ^= 1 << (bit % 8);
+            assert_ne!(ArithmeticCoder.decode(&trailing).ok(), Some(data.clone()));
+        }
+    }
+
+    #[test]
+    fn test_range_coder() {
+        // A skewed table with a total close to the highest PPM total, and an adaptive one
+        let mut skewed = SimpleFrequencyTable::new(4);
+        for (symbol, frequency) in [1, 1 << 30, 3, 0].iter().enumerate() {
+            skewed.set(symbol as u16, *frequency);
+        }
+        let symbols: Vec<u16> = (0..5000u32).map(|i| ((i * i) % 251) as u16).collect();
+
+        let mut encoder = RangeEncoder::new(Vec::new());
+        let mut adaptive = FenwickFrequencyTable::new(256);
+        for symbol in 0..256 {
+            adaptive.set(symbol, 1);
+        }
+        for (i, symbol) in symbols.iter().enumerate() {
+            encoder.write(&mut skewed, [0, 1, 1, 2][i % 4]).unwrap();
+            encoder.write(&mut adaptive, *symbol).unwrap();
+            adaptive.increment_limited(*symbol, 1 << 16);
+        }
+        encoder.finish().unwrap();
+        let encoded = encoder.inner_ref().clone();
+
+        let mut decoder = RangeDecoder::new(encoded.as_slice()).unwrap();
+        let mut adaptive = FenwickFrequencyTable::new(256);
+        for symbol in 0..256 {
+            adaptive.set(symbol, 1);
+        }
+        for (i, symbol) in symbols.iter().enumerate() {
+            assert_eq!(decoder.read(&mut skewed).unwrap(), [0, 1, 1, 2][i % 4]);
+            assert_eq!(decoder.read(&mut adaptive).unwrap(), *symbol);
+            adaptive.increment_limited(*symbol, 1 << 16);
+        }
+        assert!(decoder.check_end().is_ok());
+        assert!((0..100).any(|_| decoder.read(&mut adaptive).is_err()));
+        assert!(RangeDecoder::new(&encoded[..4]).is_err());
     }
 
-    fn increment(&mut self, _symbol: Symbol) {
-        unimplemented!()
+    #[test]
+    fn test_fenwick_table() {
+        let mut simple = SimpleFrequencyTable::new(257);
+        let mut fenwick = FenwickFrequencyTable::new(257);
+        for i in 0..2000u32 {
+            let symbol = ((i * i + 7 * i) % 257) as u16;
+            if i % 5 == 0 {
+                simple.set(symbol, (i % 13) as usize);
+                fenwick.set(symbol, (i % 13) as usize);
+            } else {
+                simple.increment(symbol);
+                fenwick.increment(symbol);
+            }
+        }
+
+        assert_eq!(simple.total(), fenwick.total());
+        for symbol in 0..257 {
+            assert_eq!(simple.get(symbol), fenwick.get(symbol));
+            assert_eq!(simple.get_low(symbol), fenwick.get_low(symbol));
+            assert_eq!(simple.get_high(symbol), fenwick.get_high(symbol));
+        }
+        for value in 0..fenwick.total() {
+            assert_eq!(simple.find(value), fenwick.find(value));
+        }
     }
 
-    #[inline]
-    fn total(&self) -> usize {
-        self.num_symbols as usize
+    #[test]
+    fn test_masked_table() {
+        let mut freqs = SimpleFrequencyTable::new(6);
+        for (symbol, frequency) in [3, 0, 2, 5, 1, 4].iter().enumerate() {
+            freqs.set(symbol as u16, *frequency);
+        }
+        let mut excluded = SymbolSet::new(6);
+        excluded.insert(3);
+        excluded.insert(0);
+        excluded.insert(3);
+
+        let masked = MaskedFrequencyTable::new(&freqs, &excluded);
+        assert_eq!(masked.total(), 7);
+        let expected = [
+            (0, 0, 0),
+            (0, 0, 0),
+            (2, 0, 2),
+            (0, 2, 2),
+            (1, 2, 3),
+            (4, 3, 7),
+        ];
+        for (symbol, (frequency, low, high)) in expected.iter().enumerate() {
+            let symbol = symbol as u16;
+            assert_eq!(masked.get(symbol), *frequency);
+            assert_eq!(masked.get_low(symbol), *low);
+            assert_eq!(masked.get_high(symbol), *high);
+        }
+        assert_eq!(masked.find(0), 2);
+        assert_eq!(masked.find(2), 4);
+        assert_eq!(masked.find(6), 5);
+
+        let flat = FlatFrequencyTable::new(6);
+        let masked = MaskedFrequencyTable::new(&flat, &excluded);
+        assert_eq!(masked.total(), 4);
+        assert_eq!(masked.get_low(5), 3);
+    }
+
+    #[test]
+    fn test_rescale() {
+        let mut freqs = FenwickFrequencyTable::new(4);
+        freqs.set(0, 1);
+        freqs.set(1, 6);
+        for _ in 0..100 {
+            freqs.increment_limited(2, 64);
+            assert!(freqs.total() <= 64);
+        }
+        // Rare symbols stay codable while frequent ones dominate
+        assert_eq!(freqs.get(0), 1);
+        assert!(freqs.get(1) > 0);
+        assert_eq!(freqs.get(3), 0);
+        assert!(freqs.get(2) > 32);
+
+        // Long inputs no longer overflow the coder
+        let data = vec![7u8; 1 << 20];
+        let encoded = ArithmeticCoder.encode(&data).unwrap();
+        assert_eq!(ArithmeticCoder.decode(&encoded).unwrap(), data);
     }
 }
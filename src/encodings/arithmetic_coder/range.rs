@@ -0,0 +1,191 @@
+use std::cmp;
+use std::io::{Error, ErrorKind, Read, Result, Write};
+
+use crate::encodings::arithmetic_coder::Symbol;
+use crate::error::ComprsError;
+
+use super::{EntropyDecoder, EntropyEncoder, FrequencyTable};
+
+// Carry-less range coder after Dmitry Subbotin, with a 64 bit state. Bytes are emitted
+// once the top byte of the range is settled. If the range gets too small while its top
+// byte is still undecided, it is cut at the next boundary below, so that no carry can
+// ever reach bytes which were already written.
+
+/// Bytes are shifted out while the top byte of low and high is the same
+const TOP: u64 = 1 << 56;
+/// Lowest range after normalization
+const BOTTOM: u64 = 1 << 48;
+/// Highest frequency total, leaves at least 16 bits of precision per symbol
+pub const MAXIMUM_TOTAL: usize = 1 << 32;
+
+fn check_total(total: usize) -> Result<()> {
+    if total > MAXIMUM_TOTAL {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "frequency total exceeds the coder limit",
+        ));
+    }
+    Ok(())
+}
+
+pub struct RangeEncoder<W: Write> {
+    writer: W,
+    low: u64,
+    range: u64,
+}
+
+impl<W: Write> RangeEncoder<W> {
+    pub fn new(writer: W) -> Self {
+        RangeEncoder {
+            writer,
+            low: 0,
+            range: u64::MAX,
+        }
+    }
+
+    pub fn write<T: FrequencyTable>(&mut self, freqtable: &mut T, symbol: Symbol) -> Result<()> {
+        let total = freqtable.total();
+        check_total(total)?;
+        let (symlow, symhigh) = freqtable.interval(symbol);
+        let (symlow, frequency) = (symlow as u64, (symhigh - symlow) as u64);
+        debug_assert!(frequency > 0, "symbol has zero frequency");
+
+        let r = self.range / total as u64;
+        self.low += r * symlow;
+        self.range = r * frequency;
+        self.normalize()
+    }
+
+    fn normalize(&mut self) -> Result<()> {
+        loop {
+            if (self.low ^ self.low.wrapping_add(self.range)) >= TOP {
+                if self.range >= BOTTOM {
+                    return Ok(());
+                }
+                self.range = self.low.wrapping_neg() & (BOTTOM - 1);
+            }
+            self.writer.write_all(&[(self.low >> 56) as u8])?;
+            self.low <<= 8;
+            self.range <<= 8;
+        }
+    }
+
+    /// Writes the remaining state, so that the output can be decoded properly.
+    /// It is important that this method must be called at the end of the each encoding process.
+    pub fn finish(&mut self) -> Result<()> {
+        self.writer.write_all(&self.low.to_be_bytes())
+    }
+
+    /// Get reference of the inner writer
+    #[inline]
+    pub fn inner_ref(&mut self) -> &W {
+        &self.writer
+    }
+}
+
+pub struct RangeDecoder<R: Read> {
+    reader: R,
+    low: u64,
+    range: u64,
+    code: u64,
+    /// Zero bytes which may still be read past the end of the input
+    padding: usize,
+}
+
+impl<R: Read> RangeDecoder<R> {
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut code = [0; 8];
+        reader.read_exact(&mut code)?;
+        Ok(RangeDecoder {
+            reader,
+            low: 0,
+            range: u64::MAX,
+            code: u64::from_be_bytes(code),
+            padding: code.len(),
+        })
+    }
+
+    pub fn read<T: FrequencyTable>(&mut self, freqtable: &mut T) -> Result<Symbol> {
+        let total = freqtable.total();
+        check_total(total)?;
+
+        let r = self.range / total as u64;
+        // The part of the range above r * total belongs to the last symbol
+        let value = cmp::min(self.code.wrapping_sub(self.low) / r, total as u64 - 1);
+        let (symbol, symlow, symhigh) = freqtable.find_interval(value as usize);
+        let (symlow, frequency) = (symlow as u64, (symhigh - symlow) as u64);
+        if frequency == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "decoded symbol has zero frequency",
+            ));
+        }
+
+        self.low += r * symlow;
+        self.range = r * frequency;
+        self.normalize()?;
+        Ok(symbol)
+    }
+
+    fn normalize(&mut self) -> Result<()> {
+        loop {
+            if (self.low ^ self.low.wrapping_add(self.range)) >= TOP {
+                if self.range >= BOTTOM {
+                    return Ok(());
+                }
+                self.range = self.low.wrapping_neg() & (BOTTOM - 1);
+            }
+            self.code = self.code << 8 | u64::from(self.read_byte()?);
+            self.low <<= 8;
+            self.range <<= 8;
+        }
+    }
+
+    /// Checks that the code equals the low end written by the encoder's `finish`
+    pub fn check_end(&self) -> Result<()> {
+        if self.code != self.low {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                ComprsError::corrupt("range coded data has a bad end"),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Reads the next byte, the input is padded with zeros for at most a full state like
+    /// the bitwise decoder does
+    fn read_byte(&mut self) -> Result<u8> {
+        let mut byte = [0];
+        match self.reader.read(&mut byte) {
+            Ok(0) if self.padding == 0 => Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                ComprsError::corrupt("range coded data ended early"),
+            )),
+            Ok(0) => {
+                self.padding -= 1;
+                Ok(0)
+            }
+            Ok(_) => Ok(byte[0]),
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => self.read_byte(),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl<W: Write> EntropyEncoder for RangeEncoder<W> {
+    #[inline]
+    fn write<T: FrequencyTable>(&mut self, freqtable: &mut T, symbol: Symbol) -> Result<()> {
+        RangeEncoder::write(self, freqtable, symbol)
+    }
+}
+
+impl<R: Read> EntropyDecoder for RangeDecoder<R> {
+    #[inline]
+    fn read<T: FrequencyTable>(&mut self, freqtable: &mut T) -> Result<Symbol> {
+        RangeDecoder::read(self, freqtable)
+    }
+
+    fn check_end(&self) -> Result<()> {
+        RangeDecoder::check_end(self)
+    }
+}
@@ -0,0 +1,306 @@
+use std::cmp::Ordering;
+
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use rayon::prelude::{ParallelSlice, ParallelSliceMut};
+
+use crate::codec::Codec;
+use crate::error::{ComprsError, Result};
+
+mod sais;
+
+/// Default size of the chunks which are sorted independently
+pub const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Burrows-Wheeler transform over chunks of the given size
+#[derive(Clone, Copy, Debug)]
+pub struct Bwt {
+    pub chunk_size: usize,
+}
+
+impl Default for Bwt {
+    fn default() -> Self {
+        Bwt {
+            chunk_size: CHUNK_SIZE,
+        }
+    }
+}
+
+impl Codec for Bwt {
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        apply(data, self.chunk_size)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        reduce(data)
+    }
+}
+
+pub fn apply(data: &[u8], chunk_size: usize) -> Result<Vec<u8>> {
+    if chunk_size == 0 {
+        return Err(ComprsError::InvalidTransform(String::from(
+            "BWT chunk size must be positive",
+        )));
+    }
+
+    // Create chunks and encode them
+    let chunks: Vec<BWTChunk> = data.par_chunks(chunk_size).map(BWTChunk::encode).collect();
+    debug!("DEBUG:BWT: split up into {} chunks", chunks.len());
+
+    // Serialize encoded data to u8
+    Ok(bincode::serialize(&BWTData { chunks })?)
+}
+
+pub fn reduce(data: &[u8]) -> Result<Vec<u8>> {
+    // Create chunks and encode them
+    let data: BWTData = bincode::deserialize(data)?;
+    debug!("DEBUG:BWT: got {} chunks", data.chunks.len());
+
+    let mut decoded = Vec::new();
+    for chunk in data.chunks {
+        decoded.extend(chunk.decode()?);
+    }
+    Ok(decoded)
+}
+
+/// Transforms a single block, returns the last column and the row of the input
+pub fn transform(data: &[u8]) -> (Vec<u8>, u32) {
+    let chunk = BWTChunk::encode(data);
+    (chunk.data, chunk.index)
+}
+
+/// Reverses `transform`
+pub fn inverse(data: Vec<u8>, index: u32) -> Result<Vec<u8>> {
+    BWTChunk { data, index }.decode()
+}
+
+struct BWTReconstructData {
+    pub position: u32,
+    pub char: u8,
+}
+
+impl Ord for BWTReconstructData {
+    #[inline]
+    fn cmp(&self, other: &BWTReconstructData) -> Ordering {
+        self.char.cmp(&other.char)
+    }
+}
+
+impl PartialOrd for BWTReconstructData {
+    #[inline]
+    fn partial_cmp(&self, other: &BWTReconstructData) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for BWTReconstructData {
+    #[inline]
+    fn eq(&self, other: &BWTReconstructData) -> bool {
+        self.char == other.char
+    }
+}
+
+impl Eq for BWTReconstructData {}
+
+#[derive(Serialize, Deserialize)]
+struct BWTData {
+    pub chunks: Vec<BWTChunk>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BWTChunk {
+    pub data: Vec<u8>,
+    pub index: u32,
+}
+
+impl BWTChunk {
+    pub fn encode(data: &[u8]) -> Self {
+        let len = data.len();
+        if len == 0 {
+            return BWTChunk {
+                data: Vec::new(),
+                index: 0,
+            };
+        }
+
+        // Sorting the suffixes of the doubled input orders all rotations
+        let mut doubled = Vec::with_capacity(2 * len);
+        doubled.extend_from_slice(data);
+        doubled.extend_from_slice(data);
+        let rotations: Vec<u32> = sais::suffix_array(&doubled)
+            .into_iter()
+            .filter(|&i| (i as usize) < len)
+            .collect();
+
+        // Create encoded data by using the last element in each row
+        let data: Vec<u8> = rotations
+            .iter()
+            .map(|&i| data[(i as usize + len - 1) % len])
+            .collect();
+
+        // Identical rotations of a periodic input are sorted by descending start position,
+        // the index has to point to the first one of them.
+        let row = rotations.iter().position(|&i| i == 0).unwrap();
+        let index = (row + 1 - len / period(&doubled[..len])) as u32;
+
+        BWTChunk { data, index }
+    }
+
+    fn decode(self) -> Result<Vec<u8>> {
+        let len = self.data.len();
+        if self.index as usize >= len && len > 0 {
+            return Err(ComprsError::corrupt("BWT index out of range"));
+        }
+
+        // Save all characters with along with position
+        let mut table: Vec<BWTReconstructData> = self
+            .data
+            .par_iter()
+            .enumerate()
+            .map(|(i, c)| BWTReconstructData {
+                position: i as u32,
+                char: *c,
+            })
+            .collect();
+
+        table.par_sort();
+
+        // Build decoded content
+        let mut decoded = Vec::with_capacity(len);
+        let mut idx: usize = self.index as usize;
+        for _ in 0..len {
+            decoded.push(table[idx].char);
+            idx = table[idx].position as usize;
+        }
+        Ok(decoded)
+    }
+}
+
+/// Returns the length of the shortest period that evenly divides `data`.
+fn period(data: &[u8]) -> usize {
+    // Compute the border of the whole input with the KMP failure function
+    let len = data.len();
+    let mut failure = vec![0; len];
+    let mut border = 0;
+    for i in 1..len {
+        while border > 0 && data[i] != data[border] {
+            border = failure[border - 1];
+        }
+        if data[i] == data[border] {
+            border += 1;
+        }
+        failure[i] = border;
+    }
+
+    let period = len - border;
+    if len % period == 0 {
+        period
+    } else {
+        len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ComprsError;
+
+    use super::{apply, reduce, BWTChunk, CHUNK_SIZE};
+
+    /// Reference implementation which sorts all rotations
+    fn naive_encode(input: &[u8]) -> BWTChunk {
+        let len = input.len();
+        let rotation =
+            |i: usize| -> Vec<u8> { input[i..].iter().chain(&input[..i]).cloned().collect() };
+        let mut rotations: Vec<usize> = (0..len).collect();
+        rotations.sort_by_key(|&i| rotation(i));
+        BWTChunk {
+            data: rotations
+                .iter()
+                .map(|&i| input[(i + len - 1) % len])
+                .collect(),
+            index: rotations.iter().position(|&i| i == 0).unwrap() as u32,
+        }
+    }
+
+    #[test]
+    fn test_apply() {
+        let test_data = vec![
+            1, 0, 0, 0, 0, 0, 0, 0, 19, 0, 0, 0, 0, 0, 0, 0, 83, 83, 51, 46, 46, 49, 50, 46, 46,
+            78, 78, 78, 78, 65, 65, 65, 65, 65, 65, 2, 0, 0, 0,
+        ];
+
+        let data = String::from(".ANANAS..ANANAS.123").into_bytes();
+        assert_eq!(apply(&data, CHUNK_SIZE).unwrap(), test_data);
+        assert!(matches!(
+            apply(&data, 0),
+            Err(ComprsError::InvalidTransform(_))
+        ));
+    }
+
+    #[test]
+    fn test_reduce() {
+        let test_data = vec![
+            1, 0, 0, 0, 0, 0, 0, 0, 19, 0, 0, 0, 0, 0, 0, 0, 83, 83, 51, 46, 46, 49, 50, 46, 46,
+            78, 78, 78, 78, 65, 65, 65, 65, 65, 65, 2, 0, 0, 0,
+        ];
+
+        let result = reduce(&test_data).unwrap();
+        assert_eq!(result, String::from(".ANANAS..ANANAS.123").into_bytes());
+    }
+
+    #[test]
+    fn test_reduce_corrupt() {
+        let mut test_data = vec![
+            1, 0, 0, 0, 0, 0, 0, 0, 19, 0, 0, 0, 0, 0, 0, 0, 83, 83, 51, 46, 46, 49, 50, 46, 46,
+            78, 78, 78, 78, 65, 65, 65, 65, 65, 65, 2, 0, 0, 0,
+        ];
+        assert!(reduce(&test_data[..30]).is_err());
+
+        test_data[35] = 19;
+        assert!(reduce(&test_data).is_err());
+    }
+
+    #[test]
+    fn test_encode() {
+        let input: Vec<u8> = String::from(".ANANAS.").into_bytes();
+        let result = BWTChunk::encode(&input);
+        assert_eq!(result.index, 1);
+        assert_eq!(
+            String::from("S..NNAAA"),
+            String::from_utf8(result.data).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_encode_repetitive() {
+        let inputs: Vec<Vec<u8>> = vec![
+            vec![0; 4096],
+            String::from("abab").into_bytes(),
+            String::from("abcabcabcab").into_bytes(),
+            (0..5000).map(|i| (i % 13) as u8).collect(),
+            (0..5000)
+                .map(|i| if i % 100 == 0 { 1 } else { 0 })
+                .collect(),
+        ];
+
+        for input in inputs {
+            let expected = naive_encode(&input);
+            let result = BWTChunk::encode(&input);
+            assert_eq!(result.index, expected.index);
+            assert_eq!(result.data, expected.data);
+            assert_eq!(result.decode().unwrap(), input);
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        let input: Vec<u8> = String::from("S..NNAAA").into_bytes();
+        let chunk = BWTChunk {
+            data: input,
+            index: 1,
+        };
+        assert_eq!(
+            String::from(".ANANAS."),
+            String::from_utf8(chunk.decode().unwrap()).unwrap()
+        );
+    }
+}
@@ -0,0 +1,205 @@
+// Suffix array construction by induced sorting (SA-IS)
+// Nong, Zhang & Chan: "Two Efficient Algorithms for Linear Time Suffix Array Construction"
+
+const EMPTY: u32 = u32::MAX;
+
+/// Returns the suffix array of `data` in O(n) time.
+pub fn suffix_array(data: &[u8]) -> Vec<u32> {
+    debug_assert!(data.len() < EMPTY as usize);
+
+    // Shift the alphabet by one to make room for a unique sentinel
+    let mut text: Vec<u32> = Vec::with_capacity(data.len() + 1);
+    text.extend(data.iter().map(|b| u32::from(*b) + 1));
+    text.push(0);
+
+    let mut sa = sais(&text, 257);
+    // The sentinel suffix is always sorted first
+    sa.remove(0);
+    sa
+}
+
+/// Builds the suffix array of `text`, which must be terminated by a unique 0 symbol.
+/// All symbols have to be smaller than `alphabet_size`.
+fn sais(text: &[u32], alphabet_size: usize) -> Vec<u32> {
+    let n = text.len();
+    if n == 1 {
+        return vec![0];
+    }
+
+    let types = classify(text);
+    let is_lms = |i: usize| i > 0 && types[i] && !types[i - 1];
+
+    // Step 1: place LMS suffixes at the end of their buckets and induce a first ordering
+    let mut sa = vec![EMPTY; n];
+    let mut tails = bucket_tails(text, alphabet_size);
+    for i in (1..n).rev().filter(|i| is_lms(*i)) {
+        let c = text[i] as usize;
+        tails[c] -= 1;
+        sa[tails[c] as usize] = i as u32;
+    }
+    induce(text, &types, &mut sa, alphabet_size);
+
+    // Step 2: move the sorted LMS substrings to the front and name them
+    let mut num_lms = 0;
+    for i in 0..n {
+        let pos = sa[i] as usize;
+        if is_lms(pos) {
+            sa[num_lms] = pos as u32;
+            num_lms += 1;
+        }
+    }
+
+    let mut names = vec![EMPTY; n];
+    let mut num_names = 0;
+    let mut prev: Option<usize> = None;
+    for &pos in &sa[..num_lms] {
+        let pos = pos as usize;
+        let equal = match prev {
+            Some(prev) => lms_substrings_equal(text, &types, prev, pos),
+            None => false,
+        };
+        if !equal {
+            num_names += 1;
+        }
+        names[pos] = num_names - 1;
+        prev = Some(pos);
+    }
+
+    // Step 3: sort the reduced string, recursing if LMS substrings are not unique
+    let lms_positions: Vec<u32> = (1..n).filter(|i| is_lms(*i)).map(|i| i as u32).collect();
+    let reduced: Vec<u32> = lms_positions.iter().map(|p| names[*p as usize]).collect();
+    drop(names);
+
+    let reduced_sa = if num_names as usize == num_lms {
+        let mut reduced_sa = vec![0; num_lms];
+        for (i, name) in reduced.iter().enumerate() {
+            reduced_sa[*name as usize] = i as u32;
+        }
+        reduced_sa
+    } else {
+        sais(&reduced, num_names as usize)
+    };
+
+    // Step 4: place LMS suffixes in their final order and induce the rest
+    for entry in sa.iter_mut() {
+        *entry = EMPTY;
+    }
+    let mut tails = bucket_tails(text, alphabet_size);
+    for &i in reduced_sa.iter().rev() {
+        let pos = lms_positions[i as usize];
+        let c = text[pos as usize] as usize;
+        tails[c] -= 1;
+        sa[tails[c] as usize] = pos;
+    }
+    induce(text, &types, &mut sa, alphabet_size);
+    sa
+}
+
+/// Classifies each suffix as S-type (`true`) or L-type (`false`).
+fn classify(text: &[u32]) -> Vec<bool> {
+    let n = text.len();
+    let mut types = vec![false; n];
+    types[n - 1] = true;
+    for i in (0..n - 1).rev() {
+        types[i] = text[i] < text[i + 1] || (text[i] == text[i + 1] && types[i + 1]);
+    }
+    types
+}
+
+fn bucket_sizes(text: &[u32], alphabet_size: usize) -> Vec<u32> {
+    let mut sizes = vec![0; alphabet_size];
+    for c in text {
+        sizes[*c as usize] += 1;
+    }
+    sizes
+}
+
+fn bucket_heads(text: &[u32], alphabet_size: usize) -> Vec<u32> {
+    let mut sum = 0;
+    bucket_sizes(text, alphabet_size)
+        .into_iter()
+        .map(|size| {
+            sum += size;
+            sum - size
+        })
+        .collect()
+}
+
+fn bucket_tails(text: &[u32], alphabet_size: usize) -> Vec<u32> {
+    let mut sum = 0;
+    bucket_sizes(text, alphabet_size)
+        .into_iter()
+        .map(|size| {
+            sum += size;
+            sum
+        })
+        .collect()
+}
+
+/// Induces the order of L-type suffixes from left to right, then S-type suffixes from right to left.
+fn induce(text: &[u32], types: &[bool], sa: &mut [u32], alphabet_size: usize) {
+    let mut heads = bucket_heads(text, alphabet_size);
+    for i in 0..sa.len() {
+        let pos = sa[i];
+        if pos != EMPTY && pos > 0 && !types[pos as usize - 1] {
+            let c = text[pos as usize - 1] as usize;
+            sa[heads[c] as usize] = pos - 1;
+            heads[c] += 1;
+        }
+    }
+
+    let mut tails = bucket_tails(text, alphabet_size);
+    for i in (0..sa.len()).rev() {
+        let pos = sa[i];
+        if pos != EMPTY && pos > 0 && types[pos as usize - 1] {
+            let c = text[pos as usize - 1] as usize;
+            tails[c] -= 1;
+            sa[tails[c] as usize] = pos - 1;
+        }
+    }
+}
+
+/// Compares the LMS substrings starting at `a` and `b`, including their terminating LMS character.
+fn lms_substrings_equal(text: &[u32], types: &[bool], a: usize, b: usize) -> bool {
+    let is_lms = |i: usize| i > 0 && types[i] && !types[i - 1];
+    let mut offset = 0;
+    loop {
+        let (i, j) = (a + offset, b + offset);
+        if text[i] != text[j] || types[i] != types[j] {
+            return false;
+        }
+        if offset > 0 && (is_lms(i) || is_lms(j)) {
+            return is_lms(i) && is_lms(j);
+        }
+        offset += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::suffix_array;
+
+    fn naive_suffix_array(data: &[u8]) -> Vec<u32> {
+        let mut sa: Vec<u32> = (0..data.len() as u32).collect();
+        sa.sort_by(|a, b| data[*a as usize..].cmp(&data[*b as usize..]));
+        sa
+    }
+
+    #[test]
+    fn test_suffix_array() {
+        let inputs: Vec<Vec<u8>> = vec![
+            vec![],
+            vec![42],
+            String::from("banana").into_bytes(),
+            String::from("mmiissiissiippii").into_bytes(),
+            String::from(".ANANAS..ANANAS.123").into_bytes(),
+            vec![0; 1000],
+            (0..2000).map(|i| (i % 7) as u8).collect(),
+            (0..3000).map(|i| ((i * i) % 251) as u8).collect(),
+        ];
+
+        for input in inputs {
+            assert_eq!(suffix_array(&input), naive_suffix_array(&input));
+        }
+    }
+}
@@ -0,0 +1,687 @@
+use std::cmp;
+use std::io::{BufReader, Read, Write};
+
+use crate::codec::Codec;
+use crate::error::{ComprsError, Result};
+
+use super::huffman::bits::{BitSink, BitSource, BitWriter, StreamReader};
+use super::huffman::{self, Decoder, Encoder};
+use super::{bwt, mtf};
+
+// The bzip2 file format, compatible with bzip2 0.9.5 and later
+
+const STREAM_MAGIC: &[u8; 3] = b"BZh";
+const BLOCK_MAGIC: u64 = 0x3141_5926_5359;
+const END_MAGIC: u64 = 0x1772_4538_5090;
+/// Block size in units of 100000 bytes
+pub const MAX_LEVEL: u8 = 9;
+const BLOCK_UNIT: usize = 100_000;
+/// Space left in a block for the run that fills it
+const BLOCK_SLACK: usize = 19;
+
+/// Runs of this many equal bytes are followed by a count of further repetitions
+const RUN_START: usize = 4;
+const MAX_RUN: usize = 255;
+
+const RUN_A: usize = 0;
+const RUN_B: usize = 1;
+/// Number of symbols coded with the same table
+const GROUP_SIZE: usize = 50;
+const MIN_TABLES: usize = 2;
+const MAX_TABLES: usize = 6;
+/// Number of refinements of the tables and the selectors
+const ITERATIONS: usize = 4;
+/// Longest code written, decoders accept up to `MAX_DECODE_LEN`
+const MAX_CODE_LEN: u32 = 17;
+const MAX_DECODE_LEN: u32 = 20;
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut crc = (n as u32) << 24;
+        let mut k = 0;
+        while k < 8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            };
+            k += 1;
+        }
+        table[n] = crc;
+        n += 1;
+    }
+    table
+}
+
+/// Returns the CRC-32 of data as used by bzip2, which processes bits most significant
+/// first unlike the one of gzip
+pub fn crc32(data: &[u8]) -> u32 {
+    update_crc32(0, data)
+}
+
+/// Continues the bzip2 CRC-32 `crc` of earlier data, which is computed most significant bit first
+pub fn update_crc32(crc: u32, data: &[u8]) -> u32 {
+    !data.iter().fold(!crc, |crc, byte| {
+        CRC_TABLE[((crc >> 24) ^ u32::from(*byte)) as usize] ^ (crc << 8)
+    })
+}
+
+/// BWT, MTF and Huffman coded data in the bzip2 file format
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bzip2 {
+    /// Block size in units of 100000 bytes, from 1 to `MAX_LEVEL`
+    pub level: u8,
+}
+
+impl Bzip2 {
+    /// Maps the compression levels 0 to 6 to block sizes of 300 to 900 kB
+    pub fn from_level(level: u8) -> Self {
+        Bzip2 {
+            level: cmp::min(level + 3, MAX_LEVEL),
+        }
+    }
+}
+
+impl Default for Bzip2 {
+    fn default() -> Self {
+        Bzip2 { level: MAX_LEVEL }
+    }
+}
+
+impl Codec for Bzip2 {
+    fn encode(&self, mut data: &[u8]) -> Result<Vec<u8>> {
+        let mut encoded = Vec::with_capacity(data.len() / 3 + 64);
+        self.encode_stream(&mut data, &mut encoded)?;
+        Ok(encoded)
+    }
+
+    /// Decodes all streams of the data and concatenates their content
+    fn decode(&self, mut data: &[u8]) -> Result<Vec<u8>> {
+        let mut decoded = Vec::new();
+        self.decode_stream(&mut data, &mut decoded)?;
+        Ok(decoded)
+    }
+
+    /// Reads one block at a time, runs of equal bytes are never split between reads
+    fn encode_stream(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<u64> {
+        if self.level == 0 || self.level > MAX_LEVEL {
+            return Err(ComprsError::InvalidTransform(format!(
+                "bzip2 level {} is not between 1 and {}",
+                self.level, MAX_LEVEL
+            )));
+        }
+        let mut bits = BitWriter::new(Vec::new());
+        for byte in STREAM_MAGIC {
+            bits.write(u32::from(*byte), 8);
+        }
+        bits.write(u32::from(b'0' + self.level), 8);
+
+        let max_block = usize::from(self.level) * BLOCK_UNIT - BLOCK_SLACK;
+        let mut input = Vec::with_capacity(2 * BLOCK_UNIT);
+        let mut position = 0;
+        let mut eof = false;
+        let mut block = Vec::with_capacity(max_block + BLOCK_SLACK);
+        let mut crc = 0;
+        let mut combined = 0u32;
+        let mut written = 0;
+        loop {
+            if !eof && input.len() - position <= MAX_RUN {
+                input.drain(..position);
+                position = 0;
+                let len = reader.take(BLOCK_UNIT as u64).read_to_end(&mut input)?;
+                eof = len < BLOCK_UNIT;
+            }
+            // Runs starting before the end are complete
+            let end = if eof {
+                input.len()
+            } else {
+                input.len() - MAX_RUN
+            };
+            let len = encode_runs(&input[position..], end - position, max_block, &mut block);
+            crc = update_crc32(crc, &input[position..position + len]);
+            position += len;
+
+            let done = eof && position == input.len();
+            if block.len() >= max_block || (done && !block.is_empty()) {
+                combined = combined.rotate_left(1) ^ crc;
+                write_block(&mut bits, &block, crc);
+                block.clear();
+                crc = 0;
+                let bytes = bits.take_bytes();
+                writer.write_all(&bytes)?;
+                written += bytes.len() as u64;
+            }
+            if done {
+                break;
+            }
+        }
+
+        write_magic(&mut bits, END_MAGIC);
+        bits.write(combined, 32);
+        let bytes = bits.finish();
+        writer.write_all(&bytes)?;
+        writer.flush()?;
+        Ok(written + bytes.len() as u64)
+    }
+
+    /// Decodes all streams one block at a time and concatenates their content
+    fn decode_stream(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<u64> {
+        let mut reader = BufReader::new(reader);
+        let mut reader = StreamReader::new(&mut reader);
+        let mut written = 0;
+        loop {
+            written += read_stream(&mut reader, writer)?;
+            reader.align()?;
+            if reader.is_at_end()? {
+                writer.flush()?;
+                return Ok(written);
+            }
+        }
+    }
+}
+
+fn write_magic(writer: &mut BitWriter, magic: u64) {
+    writer.write((magic >> 24) as u32, 24);
+    writer.write(magic as u32 & 0xff_ffff, 24);
+}
+
+fn read_magic<R: BitSource>(reader: &mut R) -> Result<u64> {
+    let high = u64::from(reader.read(24)?);
+    Ok(high << 24 | u64::from(reader.read(24)?))
+}
+
+/// Replaces runs of 4 to 255 equal bytes with 4 bytes and the number of further
+/// repetitions, for the runs starting before `end` until the output reaches `max_len`.
+/// Appends to `encoded` and returns the number of bytes consumed.
+fn encode_runs(data: &[u8], end: usize, max_len: usize, encoded: &mut Vec<u8>) -> usize {
+    let mut position = 0;
+    while position < end && encoded.len() < max_len {
+        let byte = data[position];
+        let run = data[position..]
+            .iter()
+            .take(MAX_RUN)
+            .take_while(|b| **b == byte)
+            .count();
+        if run < RUN_START {
+            encoded.resize(encoded.len() + run, byte);
+        } else {
+            encoded.extend_from_slice(&[byte; RUN_START]);
+            encoded.push((run - RUN_START) as u8);
+        }
+        position += run;
+    }
+    position
+}
+
+/// Reverses `encode_runs`
+fn decode_runs(data: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(data.len());
+    let mut run = 0;
+    let mut last = None;
+    for &byte in data {
+        if run == RUN_START {
+            let repeated = last.unwrap_or(0);
+            decoded.resize(decoded.len() + usize::from(byte), repeated);
+            run = 0;
+            last = None;
+            continue;
+        }
+        if last == Some(byte) {
+            run += 1;
+        } else {
+            run = 1;
+            last = Some(byte);
+        }
+        decoded.push(byte);
+    }
+    decoded
+}
+
+/// Replaces runs of zeros of the MTF output with the bijective base 2 digits of their
+/// length, written as `RUN_A` and `RUN_B`. Other values are shifted up by one.
+fn encode_zero_runs(indices: &[u8], end_of_block: usize) -> Vec<u16> {
+    fn push_run(symbols: &mut Vec<u16>, run: usize) {
+        if run == 0 {
+            return;
+        }
+        let mut run = run - 1;
+        loop {
+            symbols.push(if run & 1 == 1 { RUN_B } else { RUN_A } as u16);
+            if run < 2 {
+                break;
+            }
+            run = (run - 2) / 2;
+        }
+    }
+
+    let mut symbols = Vec::with_capacity(indices.len() / 2 + 1);
+    let mut run = 0;
+    for &index in indices {
+        if index == 0 {
+            run += 1;
+            continue;
+        }
+        push_run(&mut symbols, run);
+        run = 0;
+        symbols.push(u16::from(index) + 1);
+    }
+    push_run(&mut symbols, run);
+    symbols.push(end_of_block as u16);
+    symbols
+}
+
+/// Chooses Huffman tables and the table for each group of symbols. Starts with tables
+/// for contiguous ranges of the alphabet and refines them by assigning each group to its
+/// cheapest table.
+fn choose_tables(symbols: &[u16], alphabet_size: usize) -> (Vec<Vec<u8>>, Vec<u8>) {
+    let num_tables = match symbols.len() {
+        0..=199 => MIN_TABLES,
+        200..=599 => 3,
+        600..=1199 => 4,
+        1200..=2399 => 5,
+        _ => MAX_TABLES,
+    };
+    let mut freqs = vec![0; alphabet_size];
+    for &symbol in symbols {
+        freqs[usize::from(symbol)] += 1;
+    }
+
+    // Each table starts out cheap for a range with an equal share of the symbols
+    let mut lengths = vec![vec![0u8; alphabet_size]; num_tables];
+    let mut remaining = symbols.len();
+    let mut start = 0;
+    for part in (1..=num_tables).rev() {
+        let target = remaining / part;
+        let mut end = start;
+        let mut sum = 0;
+        while sum < target && end < alphabet_size {
+            sum += freqs[end];
+            end += 1;
+        }
+        // Alternate between ending the range before and after the symbol which crossed
+        // the target, as bzip2 does
+        if end > start + 1 && part != num_tables && part != 1 && (num_tables - part) % 2 == 1 {
+            end -= 1;
+            sum -= freqs[end];
+        }
+        for (symbol, len) in lengths[part - 1].iter_mut().enumerate() {
+            *len = if symbol >= start && symbol < end {
+                0
+            } else {
+                15
+            };
+        }
+        start = end;
+        remaining -= sum;
+    }
+
+    let mut selectors = Vec::with_capacity(symbols.len() / GROUP_SIZE + 1);
+    for _ in 0..ITERATIONS {
+        let mut table_freqs = vec![vec![0; alphabet_size]; num_tables];
+        selectors.clear();
+        for group in symbols.chunks(GROUP_SIZE) {
+            let costs = lengths.iter().map(|table| {
+                group
+                    .iter()
+                    .map(|symbol| usize::from(table[usize::from(*symbol)]))
+                    .sum::<usize>()
+            });
+            let (best, _) = costs
+                .enumerate()
+                .min_by_key(|(table, cost)| (*cost, *table))
+                .unwrap();
+            selectors.push(best as u8);
+            for &symbol in group {
+                table_freqs[best][usize::from(symbol)] += 1;
+            }
+        }
+        // Every symbol of the alphabet needs a code
+        for (table, freqs) in lengths.iter_mut().zip(&mut table_freqs) {
+            for freq in freqs.iter_mut() {
+                *freq = cmp::max(*freq, 1);
+            }
+            *table = huffman::code_lengths(freqs, MAX_CODE_LEN);
+        }
+    }
+    (lengths, selectors)
+}
+
+fn write_block(writer: &mut BitWriter, block: &[u8], crc: u32) {
+    let (transformed, origin) = bwt::transform(block);
+
+    // The MTF stage only ranks the bytes which occur in the block
+    let mut used = [false; 256];
+    for &byte in &transformed {
+        used[usize::from(byte)] = true;
+    }
+    let mut ranks = [0u8; 256];
+    let mut num_used = 0;
+    for (byte, _) in used.iter().enumerate().filter(|(_, used)| **used) {
+        ranks[byte] = num_used as u8;
+        num_used += 1;
+    }
+    let ranked: Vec<u8> = transformed.iter().map(|b| ranks[usize::from(*b)]).collect();
+    let alphabet_size = num_used + 2;
+    let symbols = encode_zero_runs(&mtf::apply(&ranked), num_used + 1);
+
+    write_magic(writer, BLOCK_MAGIC);
+    writer.write(crc, 32);
+    // Not randomised
+    writer.write(0, 1);
+    writer.write(origin, 24);
+
+    let ranges: Vec<&[bool]> = used.chunks(16).collect();
+    for range in &ranges {
+        writer.write(range.contains(&true) as u32, 1);
+    }
+    for range in ranges.iter().filter(|range| range.contains(&true)) {
+        for &used in *range {
+            writer.write(used as u32, 1);
+        }
+    }
+
+    let (tables, selectors) = choose_tables(&symbols, alphabet_size);
+    writer.write(tables.len() as u32, 3);
+    writer.write(selectors.len() as u32, 15);
+    let mut order: Vec<u8> = (0..tables.len() as u8).collect();
+    for &selector in &selectors {
+        let index = order.iter().position(|t| *t == selector).unwrap();
+        order[..=index].rotate_right(1);
+        for _ in 0..index {
+            writer.write(1, 1);
+        }
+        writer.write(0, 1);
+    }
+    for lengths in &tables {
+        huffman::write_lengths(writer, lengths);
+    }
+
+    let encoders: Vec<Encoder> = tables.iter().map(|lengths| Encoder::new(lengths)).collect();
+    for (group, selector) in symbols.chunks(GROUP_SIZE).zip(&selectors) {
+        let encoder = &encoders[usize::from(*selector)];
+        for &symbol in group {
+            encoder.write(writer, usize::from(symbol));
+        }
+    }
+}
+
+/// Decodes one stream and writes its content, returns the number of bytes written
+fn read_stream<R: BitSource>(reader: &mut R, writer: &mut dyn Write) -> Result<u64> {
+    for byte in STREAM_MAGIC {
+        if reader.read(8)? != u32::from(*byte) {
+            return Err(ComprsError::corrupt("not in bzip2 format"));
+        }
+    }
+    let level = reader.read(8)?;
+    if level < u32::from(b'1') || level > u32::from(b'0' + MAX_LEVEL) {
+        return Err(ComprsError::corrupt("invalid bzip2 block size"));
+    }
+    let max_block = (level - u32::from(b'0')) as usize * BLOCK_UNIT;
+
+    let mut combined = 0u32;
+    let mut written = 0;
+    loop {
+        match read_magic(reader)? {
+            BLOCK_MAGIC => {
+                let expected = reader.read(32)?;
+                let block = read_block(reader, max_block)?;
+                let actual = crc32(&block);
+                if expected != actual {
+                    return Err(ComprsError::ChecksumMismatch { expected, actual });
+                }
+                combined = combined.rotate_left(1) ^ actual;
+                writer.write_all(&block)?;
+                written += block.len() as u64;
+            }
+            END_MAGIC => {
+                let expected = reader.read(32)?;
+                if expected != combined {
+                    return Err(ComprsError::ChecksumMismatch {
+                        expected,
+                        actual: combined,
+                    });
+                }
+                return Ok(written);
+            }
+            _ => return Err(ComprsError::corrupt("invalid bzip2 block header")),
+        }
+    }
+}
+
+/// Decodes one block without its header, up to `max_len` bytes before the run
+/// length decoding
+fn read_block<R: BitSource>(reader: &mut R, max_len: usize) -> Result<Vec<u8>> {
+    if reader.read(1)? == 1 {
+        return Err(ComprsError::corrupt(
+            "randomised bzip2 blocks are not supported",
+        ));
+    }
+    let origin = reader.read(24)?;
+
+    let ranges = reader.read(16)?;
+    let mut bytes = Vec::with_capacity(256);
+    for range in (0..16).filter(|range| ranges >> (15 - range) & 1 == 1) {
+        let used = reader.read(16)?;
+        for byte in (0..16).filter(|byte| used >> (15 - byte) & 1 == 1) {
+            bytes.push((range * 16 + byte) as u8);
+        }
+    }
+    if bytes.is_empty() {
+        return Err(ComprsError::corrupt("bzip2 block uses no bytes"));
+    }
+    let end_of_block = bytes.len() + 1;
+    let alphabet_size = bytes.len() + 2;
+
+    let num_tables = reader.read(3)? as usize;
+    if !(MIN_TABLES..=MAX_TABLES).contains(&num_tables) {
+        return Err(ComprsError::corrupt("invalid number of bzip2 tables"));
+    }
+    let num_selectors = reader.read(15)? as usize;
+    if num_selectors == 0 {
+        return Err(ComprsError::corrupt("bzip2 block has no selectors"));
+    }
+    let mut order: Vec<u8> = (0..num_tables as u8).collect();
+    let mut selectors = Vec::with_capacity(num_selectors);
+    for _ in 0..num_selectors {
+        let mut index = 0;
+        while reader.read(1)? == 1 {
+            index += 1;
+            if index >= num_tables {
+                return Err(ComprsError::corrupt("invalid bzip2 selector"));
+            }
+        }
+        order[..=index].rotate_right(1);
+        selectors.push(order[0]);
+    }
+
+    let mut decoders = Vec::with_capacity(num_tables);
+    for _ in 0..num_tables {
+        // Every symbol of the alphabet has a code
+        let lengths = huffman::read_lengths(reader, alphabet_size, 1, MAX_DECODE_LEN)?;
+        decoders.push(Decoder::new(&lengths)?);
+    }
+
+    let mut indices = Vec::with_capacity(max_len);
+    let mut run = 0;
+    let mut weight = 1;
+    let mut count = 0;
+    loop {
+        let selector = selectors
+            .get(count / GROUP_SIZE)
+            .ok_or_else(|| ComprsError::corrupt("bzip2 block has too few selectors"))?;
+        let symbol = decoders[usize::from(*selector)].read(reader)?;
+        count += 1;
+        if symbol == RUN_A || symbol == RUN_B {
+            run += weight << symbol;
+            weight <<= 1;
+            if run > max_len {
+                return Err(ComprsError::corrupt("bzip2 block is too long"));
+            }
+            continue;
+        }
+        if indices.len() + run > max_len {
+            return Err(ComprsError::corrupt("bzip2 block is too long"));
+        }
+        indices.resize(indices.len() + run, 0);
+        run = 0;
+        weight = 1;
+        if symbol == end_of_block {
+            break;
+        }
+        if indices.len() == max_len {
+            return Err(ComprsError::corrupt("bzip2 block is too long"));
+        }
+        indices.push((symbol - 1) as u8);
+    }
+
+    if origin as usize >= indices.len() {
+        return Err(ComprsError::corrupt("BWT index out of range"));
+    }
+    let transformed = mtf::reduce(&indices)
+        .iter()
+        .map(|rank| bytes[usize::from(*rank)])
+        .collect();
+    Ok(decode_runs(&bwt::inverse(transformed, origin)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codec::Codec;
+    use crate::error::ComprsError;
+    use crate::utils::random_bytes;
+
+    use super::{crc32, decode_runs, encode_runs, encode_zero_runs, Bzip2, MAX_LEVEL, MAX_RUN};
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xfc89_1918);
+    }
+
+    #[test]
+    fn test_runs() {
+        let data = [vec![1; 3], vec![2; 4], vec![3; 300], vec![4]].concat();
+        let mut encoded = Vec::new();
+        let len = encode_runs(&data, data.len(), 1000, &mut encoded);
+        assert_eq!(len, data.len());
+        assert_eq!(
+            encoded,
+            [1, 1, 1, 2, 2, 2, 2, 0, 3, 3, 3, 3, 251, 3, 3, 3, 3, 41, 4]
+        );
+        assert_eq!(decode_runs(&encoded), data);
+
+        // The block ends after the run which reaches the limit
+        let mut encoded = Vec::new();
+        let len = encode_runs(&data, data.len(), 9, &mut encoded);
+        assert_eq!(len, 262);
+        assert_eq!(decode_runs(&encoded), &data[..262]);
+
+        // Runs starting before the end are consumed completely
+        let mut encoded = vec![1];
+        let len = encode_runs(&data, 8, 1000, &mut encoded);
+        assert_eq!(len, 262);
+        assert_eq!(decode_runs(&encoded[1..]), &data[..262]);
+    }
+
+    #[test]
+    fn test_zero_runs() {
+        let symbols = encode_zero_runs(&[0, 1, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0], 9);
+        // Runs of 1, 2 and 7 zeros
+        assert_eq!(symbols, [0, 2, 1, 3, 0, 0, 0, 9]);
+    }
+
+    #[test]
+    fn test_decode_bzip2() {
+        // Written by Python's bz2 module
+        let stream = [
+            0x42, 0x5a, 0x68, 0x39, 0x31, 0x41, 0x59, 0x26, 0x53, 0x59, 0x4c, 0xd6, 0x5b, 0x62,
+            0x00, 0x00, 0x02, 0x59, 0x80, 0x00, 0x10, 0x40, 0x00, 0x10, 0x00, 0x32, 0x22, 0x5c,
+            0x10, 0x20, 0x00, 0x22, 0x00, 0x03, 0x42, 0x01, 0xa0, 0x0a, 0x6b, 0xa7, 0xc1, 0x7a,
+            0x34, 0x0f, 0x17, 0x72, 0x45, 0x38, 0x50, 0x90, 0x4c, 0xd6, 0x5b, 0x62,
+        ];
+        assert_eq!(Bzip2::default().decode(&stream).unwrap(), b"bzip2 stream\n");
+
+        // Concatenated streams are decoded as one
+        let mut streams = stream.to_vec();
+        streams.extend_from_slice(&Bzip2::default().encode(b"second").unwrap());
+        assert_eq!(
+            Bzip2::default().decode(&streams).unwrap(),
+            b"bzip2 stream\nsecond"
+        );
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let inputs: Vec<Vec<u8>> = vec![
+            Vec::new(),
+            vec![7],
+            vec![0; 1000],
+            b"abab".repeat(1000),
+            random_bytes(250_000),
+        ];
+        for input in &inputs {
+            for level in &[1, 9] {
+                let bzip2 = Bzip2 { level: *level };
+                let encoded = bzip2.encode(input).unwrap();
+                assert_eq!(&bzip2.decode(&encoded).unwrap(), input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_stream() {
+        // Several blocks, with runs across the reads of the encoder every 100000 bytes
+        let mut input: Vec<u8> = (0..250_000u64).map(|i| ((i * i) >> 9) as u8).collect();
+        for start in &[99_900, 199_990, 200_000 - MAX_RUN] {
+            for byte in &mut input[*start..*start + 300] {
+                *byte = 5;
+            }
+        }
+        let bzip2 = Bzip2 { level: 1 };
+        let mut encoded = Vec::new();
+        let len = bzip2.encode_stream(&mut &input[..], &mut encoded).unwrap();
+        assert_eq!(len, encoded.len() as u64);
+
+        let mut streams = encoded.clone();
+        streams.extend_from_slice(&encoded);
+        let mut decoded = Vec::new();
+        let len = bzip2
+            .decode_stream(&mut &streams[..], &mut decoded)
+            .unwrap();
+        assert_eq!(len, 2 * input.len() as u64);
+        assert_eq!(decoded, [&input[..], &input[..]].concat());
+
+        let mut decoded = Vec::new();
+        let result = bzip2.decode_stream(&mut &encoded[..encoded.len() - 1], &mut decoded);
+        assert!(result.is_err());
+        let result = bzip2.decode_stream(&mut &streams[..encoded.len() + 1], &mut decoded);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let data = b"some data, some data".to_vec();
+        let encoded = Bzip2::default().encode(&data).unwrap();
+        assert_eq!(Bzip2::default().decode(&encoded).unwrap(), data);
+
+        let mut corrupt = encoded.clone();
+        corrupt[10] ^= 1;
+        match Bzip2::default().decode(&corrupt) {
+            Err(ComprsError::ChecksumMismatch { .. }) => (),
+            result => panic!("unexpected result {:?}", result),
+        }
+        let len = encoded.len();
+        assert!(Bzip2::default().decode(&encoded[..len - 2]).is_err());
+        assert!(Bzip2::default().decode(&encoded[1..]).is_err());
+        for level in &[0, MAX_LEVEL + 1] {
+            match (Bzip2 { level: *level }).encode(&data) {
+                Err(ComprsError::InvalidTransform(_)) => (),
+                result => panic!("unexpected result {:?}", result),
+            }
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+
+use crate::codec::Codec;
+use crate::error::{ComprsError, Result};
+
+use super::super::huffman::bits::{BitSource, LsbStreamReader};
+use super::{compress, compress_stream, decompress_stream, Deflate};
+
+// gzip members as specified by RFC 1952
+
+const MAGIC: [u8; 2] = [0x1f, 0x8b];
+const METHOD_DEFLATE: u8 = 8;
+const OS_UNKNOWN: u8 = 255;
+/// Header of the written members, without flags, modification time and extra flags
+const HEADER: [u8; 10] = [0x1f, 0x8b, METHOD_DEFLATE, 0, 0, 0, 0, 0, 0, OS_UNKNOWN];
+
+const FLAG_HEADER_CRC: u8 = 1 << 1;
+const FLAG_EXTRA: u8 = 1 << 2;
+const FLAG_NAME: u8 = 1 << 3;
+const FLAG_COMMENT: u8 = 1 << 4;
+const RESERVED_FLAGS: u8 = 0xe0;
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut crc = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            crc = if crc & 1 == 1 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            k += 1;
+        }
+        table[n] = crc;
+        n += 1;
+    }
+    table
+}
+
+/// Returns the CRC-32 of data as used by gzip
+pub fn crc32(data: &[u8]) -> u32 {
+    update_crc32(0, data)
+}
+
+/// Continues the gzip CRC-32 `crc` of earlier data, which is computed on reflected bits
+pub fn update_crc32(crc: u32, data: &[u8]) -> u32 {
+    !data.iter().fold(!crc, |crc, byte| {
+        CRC_TABLE[((crc ^ u32::from(*byte)) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Deflate compressed data in the gzip file format
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Gzip {
+    pub deflate: Deflate,
+}
+
+impl Codec for Gzip {
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut buffer = Vec::with_capacity(data.len() / 2 + 18);
+        buffer.extend_from_slice(&HEADER);
+        let mut buffer = compress(data, &self.deflate, buffer);
+        buffer.extend_from_slice(&crc32(data).to_le_bytes());
+        buffer.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        Ok(buffer)
+    }
+
+    /// Decodes all members of the data and concatenates their content
+    fn decode(&self, mut data: &[u8]) -> Result<Vec<u8>> {
+        let mut decoded = Vec::new();
+        self.decode_stream(&mut data, &mut decoded)?;
+        Ok(decoded)
+    }
+
+    fn encode_stream(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<u64> {
+        writer.write_all(&HEADER)?;
+        let mut reader = Checksum::new(reader);
+        let len = compress_stream(&mut reader, writer, &self.deflate)?;
+        writer.write_all(&reader.crc.to_le_bytes())?;
+        writer.write_all(&(reader.len as u32).to_le_bytes())?;
+        writer.flush()?;
+        Ok(HEADER.len() as u64 + len + 8)
+    }
+
+    /// Decodes all members and concatenates their content
+    fn decode_stream(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<u64> {
+        let mut reader = BufReader::new(reader);
+        let mut written = 0;
+        loop {
+            written += decode_member(&mut reader, writer)?;
+            if reader.fill_buf()?.is_empty() {
+                return Ok(written);
+            }
+        }
+    }
+}
+
+/// Passes data on while computing its CRC-32 and length
+struct Checksum<T> {
+    inner: T,
+    crc: u32,
+    len: u64,
+}
+
+impl<T> Checksum<T> {
+    fn new(inner: T) -> Self {
+        Checksum {
+            inner,
+            crc: 0,
+            len: 0,
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.crc = update_crc32(self.crc, data);
+        self.len += data.len() as u64;
+    }
+}
+
+impl<T: Read> Read for Checksum<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.update(&buf[..len]);
+        Ok(len)
+    }
+}
+
+impl<T: Write> Write for Checksum<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.update(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn truncated() -> ComprsError {
+    ComprsError::corrupt("truncated gzip data")
+}
+
+/// Appends the next `len` bytes of the reader to `buffer`
+fn read_into(reader: &mut dyn BufRead, buffer: &mut Vec<u8>, len: usize) -> Result<()> {
+    let start = buffer.len();
+    buffer.resize(start + len, 0);
+    reader
+        .read_exact(&mut buffer[start..])
+        .map_err(|e| match e.kind() {
+            ErrorKind::UnexpectedEof => truncated(),
+            _ => e.into(),
+        })
+}
+
+/// Appends a zero terminated string to `buffer`
+fn read_string(reader: &mut dyn BufRead, buffer: &mut Vec<u8>) -> Result<()> {
+    reader.read_until(0, buffer)?;
+    match buffer.last() {
+        Some(0) => Ok(()),
+        _ => Err(truncated()),
+    }
+}
+
+/// Decodes one member into writer, returns the length of its content
+fn decode_member(reader: &mut dyn BufRead, writer: &mut dyn Write) -> Result<u64> {
+    let mut header = Vec::with_capacity(HEADER.len());
+    read_into(reader, &mut header, HEADER.len())?;
+    if header[..2] != MAGIC {
+        return Err(ComprsError::corrupt("not in gzip format"));
+    }
+    if header[2] != METHOD_DEFLATE {
+        return Err(ComprsError::corrupt("unknown gzip compression method"));
+    }
+    let flags = header[3];
+    if flags & RESERVED_FLAGS != 0 {
+        return Err(ComprsError::corrupt("reserved gzip flags are set"));
+    }
+    if flags & FLAG_EXTRA != 0 {
+        read_into(reader, &mut header, 2)?;
+        let len = u16::from_le_bytes([header[header.len() - 2], header[header.len() - 1]]);
+        read_into(reader, &mut header, usize::from(len))?;
+    }
+    if flags & FLAG_NAME != 0 {
+        read_string(reader, &mut header)?;
+    }
+    if flags & FLAG_COMMENT != 0 {
+        read_string(reader, &mut header)?;
+    }
+    if flags & FLAG_HEADER_CRC != 0 {
+        let mut stored = Vec::with_capacity(2);
+        read_into(reader, &mut stored, 2)?;
+        let actual = crc32(&header) & 0xffff;
+        if u32::from(u16::from_le_bytes([stored[0], stored[1]])) != actual {
+            return Err(ComprsError::corrupt("gzip header checksum does not match"));
+        }
+    }
+
+    // The trailer is read through the bit reader, which may have peeked into it
+    let mut reader = LsbStreamReader::new(reader);
+    let mut writer = Checksum::new(writer);
+    let len = decompress_stream(&mut reader, &mut writer)?;
+    let expected = reader.read(32)?;
+    if expected != writer.crc {
+        return Err(ComprsError::ChecksumMismatch {
+            expected,
+            actual: writer.crc,
+        });
+    }
+    if reader.read(32)? != len as u32 {
+        return Err(ComprsError::corrupt("gzip size does not match"));
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codec::Codec;
+    use crate::error::ComprsError;
+
+    use super::{crc32, Gzip};
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_decode_gzip() {
+        // Written by Python's gzip module with a file name
+        let member = [
+            0x1f, 0x8b, 0x08, 0x08, 0x00, 0x00, 0x00, 0x00, 0x02, 0xff, 0x61, 0x2e, 0x74, 0x78,
+            0x74, 0x00, 0x4b, 0xaf, 0xca, 0x2c, 0x50, 0xc8, 0x4d, 0xcd, 0x4d, 0x4a, 0x2d, 0xe2,
+            0x02, 0x00, 0xbf, 0x03, 0xa0, 0xee, 0x0c, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(Gzip::default().decode(&member).unwrap(), b"gzip member\n");
+
+        // Concatenated members are decoded as one
+        let mut members = member.to_vec();
+        members.extend_from_slice(&Gzip::default().encode(b"second").unwrap());
+        assert_eq!(
+            Gzip::default().decode(&members).unwrap(),
+            b"gzip member\nsecond"
+        );
+    }
+
+    #[test]
+    fn test_stream() {
+        let data = "streamed in several segments ".repeat(50_000).into_bytes();
+        let gzip = Gzip::default();
+        let mut encoded = Vec::new();
+        gzip.encode_stream(&mut data.as_slice(), &mut encoded)
+            .unwrap();
+        encoded.extend_from_slice(&gzip.encode(b", second member").unwrap());
+
+        let mut decoded = Vec::new();
+        let len = gzip
+            .decode_stream(&mut encoded.as_slice(), &mut decoded)
+            .unwrap();
+        assert_eq!(len as usize, decoded.len());
+        assert_eq!(&decoded[..data.len()], data.as_slice());
+        assert_eq!(&decoded[data.len()..], b", second member");
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let data = b"some data, some data".to_vec();
+        let encoded = Gzip::default().encode(&data).unwrap();
+        assert_eq!(Gzip::default().decode(&encoded).unwrap(), data);
+
+        let mut corrupt = encoded.clone();
+        let len = corrupt.len();
+        corrupt[len - 8] ^= 1;
+        match Gzip::default().decode(&corrupt) {
+            Err(ComprsError::ChecksumMismatch { .. }) => (),
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert!(Gzip::default().decode(&encoded[..len - 1]).is_err());
+        assert!(Gzip::default().decode(&encoded[1..]).is_err());
+    }
+}
@@ -0,0 +1,786 @@
+use std::cmp;
+use std::io::{BufReader, Read, Write};
+
+use crate::codec::Codec;
+use crate::error::{ComprsError, Result};
+
+use super::huffman::bits::{BitSink, BitSource, LsbBitReader, LsbBitWriter, LsbStreamReader};
+use super::huffman::{self, Decoder, Encoder};
+use super::lz77::matcher::{self, Token};
+
+pub mod gzip;
+
+// Raw deflate streams as specified by RFC 1951
+
+const WINDOW_SIZE: usize = 1 << 15;
+const MAX_MATCH: usize = 258;
+const MAX_CODE_LEN: u32 = 15;
+const MAX_CODE_LENGTH_LEN: u32 = 7;
+/// Default number of chain entries compared per position
+pub const MAX_CHAIN: u16 = 128;
+/// Number of tokens coded with the same Huffman codes
+const BLOCK_TOKENS: usize = 1 << 15;
+/// Largest block without compression
+const MAX_STORED: usize = 0xffff;
+/// Amount of new input the streaming encoder parses at once
+const SEGMENT_SIZE: usize = 1 << 20;
+/// Amount of decoded data the streaming decoder buffers besides the window
+const OUTPUT_SIZE: usize = 1 << 20;
+
+const END_OF_BLOCK: usize = 256;
+const NUM_LITERAL_CODES: usize = 286;
+const NUM_DISTANCE_CODES: usize = 30;
+const NUM_CODE_LENGTH_CODES: usize = 19;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order in which the lengths of the code length codes are stored
+const CODE_LENGTH_ORDER: [usize; NUM_CODE_LENGTH_CODES] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Block types as stored in the block header
+const STORED: u32 = 0;
+const FIXED: u32 = 1;
+const DYNAMIC: u32 = 2;
+
+/// Raw deflate compression
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Deflate {
+    /// Number of earlier positions compared for each match
+    pub max_chain: u16,
+    /// Emit a literal instead of a match if the next position starts a longer match
+    pub lazy: bool,
+}
+
+impl Deflate {
+    /// Returns the parameters for a compression level from 0 to `preset::MAX_LEVEL`
+    pub fn from_level(level: u8) -> Self {
+        Deflate {
+            max_chain: 4 << level,
+            lazy: level > 0,
+        }
+    }
+}
+
+impl Default for Deflate {
+    fn default() -> Self {
+        Deflate {
+            max_chain: MAX_CHAIN,
+            lazy: true,
+        }
+    }
+}
+
+impl Codec for Deflate {
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(compress(data, self, Vec::new()))
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let (decoded, len) = decompress(data)?;
+        if len != data.len() {
+            return Err(trailing_data());
+        }
+        Ok(decoded)
+    }
+
+    fn encode_stream(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<u64> {
+        compress_stream(reader, writer, self)
+    }
+
+    fn decode_stream(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<u64> {
+        let mut reader = BufReader::new(reader);
+        let mut reader = LsbStreamReader::new(&mut reader);
+        let len = decompress_stream(&mut reader, writer)?;
+        if !reader.is_at_end()? {
+            return Err(trailing_data());
+        }
+        Ok(len)
+    }
+}
+
+fn trailing_data() -> ComprsError {
+    ComprsError::corrupt("trailing data after deflate stream")
+}
+
+fn fixed_literal_lengths() -> Vec<u8> {
+    let mut lengths = vec![8; 288];
+    lengths[144..256].iter_mut().for_each(|len| *len = 9);
+    lengths[256..280].iter_mut().for_each(|len| *len = 7);
+    lengths
+}
+
+fn fixed_distance_lengths() -> Vec<u8> {
+    vec![5; 32]
+}
+
+/// Returns the code of a match length or distance from the table of base values
+fn find_code(base: &[u16], value: usize) -> usize {
+    base.partition_point(|b| *b as usize <= value) - 1
+}
+
+/// Code length symbols with the value of their extra bits
+fn run_length_lengths(lengths: &[u8]) -> Vec<(u8, u8)> {
+    let mut symbols = Vec::new();
+    let mut i = 0;
+    while i < lengths.len() {
+        let len = lengths[i];
+        let run = lengths[i..].iter().take_while(|l| **l == len).count();
+        if len == 0 && run >= 3 {
+            let run = cmp::min(run, 138);
+            if run >= 11 {
+                symbols.push((18, (run - 11) as u8));
+            } else {
+                symbols.push((17, (run - 3) as u8));
+            }
+            i += run;
+        } else if len != 0 && run >= 4 {
+            // The first length is written as is, then repeated
+            symbols.push((len, 0));
+            let run = cmp::min(run - 1, 6);
+            symbols.push((16, (run - 3) as u8));
+            i += 1 + run;
+        } else {
+            symbols.push((len, 0));
+            i += 1;
+        }
+    }
+    symbols
+}
+
+fn code_length_extra_bits(symbol: u8) -> u32 {
+    match symbol {
+        16 => 2,
+        17 => 3,
+        18 => 7,
+        _ => 0,
+    }
+}
+
+/// Gives unused symbols a frequency until at least two symbols are used, as some
+/// decoders reject codes with a single symbol
+fn use_two_symbols(freqs: &mut [usize]) {
+    let used = freqs.iter().filter(|freq| **freq > 0).count();
+    for freq in freqs
+        .iter_mut()
+        .filter(|freq| **freq == 0)
+        .take(2usize.saturating_sub(used))
+    {
+        *freq = 1;
+    }
+}
+
+/// Huffman codes and header of a dynamic block
+struct DynamicCodes {
+    literals: Encoder,
+    distances: Encoder,
+    code_lengths: Encoder,
+    num_literals: usize,
+    num_distances: usize,
+    num_code_lengths: usize,
+    symbols: Vec<(u8, u8)>,
+}
+
+impl DynamicCodes {
+    fn new(literal_freqs: &[usize], distance_freqs: &[usize]) -> Self {
+        let literal_lengths = huffman::code_lengths(literal_freqs, MAX_CODE_LEN);
+        let mut distance_freqs = distance_freqs.to_vec();
+        use_two_symbols(&mut distance_freqs);
+        let distance_lengths = huffman::code_lengths(&distance_freqs, MAX_CODE_LEN);
+
+        let num_literals = cmp::max(
+            END_OF_BLOCK + 1,
+            literal_lengths
+                .iter()
+                .rposition(|len| *len > 0)
+                .unwrap_or(0)
+                + 1,
+        );
+        let num_distances = distance_lengths
+            .iter()
+            .rposition(|len| *len > 0)
+            .unwrap_or(0)
+            + 1;
+        let mut lengths = literal_lengths[..num_literals].to_vec();
+        lengths.extend_from_slice(&distance_lengths[..num_distances]);
+        let symbols = run_length_lengths(&lengths);
+
+        let mut freqs = vec![0; NUM_CODE_LENGTH_CODES];
+        for (symbol, _) in &symbols {
+            freqs[*symbol as usize] += 1;
+        }
+        use_two_symbols(&mut freqs);
+        let code_length_lengths = huffman::code_lengths(&freqs, MAX_CODE_LENGTH_LEN);
+        let num_code_lengths = cmp::max(
+            4,
+            CODE_LENGTH_ORDER
+                .iter()
+                .rposition(|symbol| code_length_lengths[*symbol] > 0)
+                .unwrap_or(0)
+                + 1,
+        );
+
+        DynamicCodes {
+            literals: Encoder::new_lsb_first(&literal_lengths),
+            distances: Encoder::new_lsb_first(&distance_lengths),
+            code_lengths: Encoder::new_lsb_first(&code_length_lengths),
+            num_literals,
+            num_distances,
+            num_code_lengths,
+            symbols,
+        }
+    }
+
+    fn header_bits(&self) -> usize {
+        let symbols: u32 = self
+            .symbols
+            .iter()
+            .map(|(symbol, _)| {
+                self.code_lengths.code_len(*symbol as usize) + code_length_extra_bits(*symbol)
+            })
+            .sum();
+        14 + 3 * self.num_code_lengths + symbols as usize
+    }
+
+    fn write_header<W: BitSink>(&self, writer: &mut W) {
+        writer.write((self.num_literals - 257) as u32, 5);
+        writer.write((self.num_distances - 1) as u32, 5);
+        writer.write((self.num_code_lengths - 4) as u32, 4);
+        for symbol in &CODE_LENGTH_ORDER[..self.num_code_lengths] {
+            writer.write(self.code_lengths.code_len(*symbol), 3);
+        }
+        for (symbol, extra) in &self.symbols {
+            self.code_lengths.write(writer, *symbol as usize);
+            writer.write(u32::from(*extra), code_length_extra_bits(*symbol));
+        }
+    }
+}
+
+/// Returns the number of bits needed for the tokens with the given codes
+fn data_bits(
+    literal_freqs: &[usize],
+    distance_freqs: &[usize],
+    literals: &Encoder,
+    distances: &Encoder,
+) -> usize {
+    let literal_bits: usize = literal_freqs
+        .iter()
+        .enumerate()
+        .filter(|(_, freq)| **freq > 0)
+        .map(|(symbol, freq)| {
+            let extra = match symbol {
+                257..=285 => u32::from(LENGTH_EXTRA[symbol - 257]),
+                _ => 0,
+            };
+            freq * (literals.code_len(symbol) + extra) as usize
+        })
+        .sum();
+    let distance_bits: usize = distance_freqs
+        .iter()
+        .enumerate()
+        .filter(|(_, freq)| **freq > 0)
+        .map(|(code, freq)| {
+            freq * (distances.code_len(code) + u32::from(DISTANCE_EXTRA[code])) as usize
+        })
+        .sum();
+    literal_bits + distance_bits
+}
+
+fn write_tokens<W: BitSink>(
+    writer: &mut W,
+    tokens: &[Token],
+    literals: &Encoder,
+    distances: &Encoder,
+) {
+    for token in tokens {
+        match *token {
+            Token::Literal(byte) => literals.write(writer, byte as usize),
+            Token::Match { length, distance } => {
+                let code = find_code(&LENGTH_BASE, length);
+                literals.write(writer, 257 + code);
+                writer.write(
+                    (length - LENGTH_BASE[code] as usize) as u32,
+                    u32::from(LENGTH_EXTRA[code]),
+                );
+                let code = find_code(&DISTANCE_BASE, distance);
+                distances.write(writer, code);
+                writer.write(
+                    (distance - DISTANCE_BASE[code] as usize) as u32,
+                    u32::from(DISTANCE_EXTRA[code]),
+                );
+            }
+        }
+    }
+    literals.write(writer, END_OF_BLOCK);
+}
+
+/// Writes one block as stored, fixed or dynamic block, whichever is smallest
+fn write_block(writer: &mut LsbBitWriter, tokens: &[Token], raw: &[u8], last: bool) {
+    let mut literal_freqs = vec![0; NUM_LITERAL_CODES];
+    let mut distance_freqs = vec![0; NUM_DISTANCE_CODES];
+    for token in tokens {
+        match *token {
+            Token::Literal(byte) => literal_freqs[byte as usize] += 1,
+            Token::Match { length, distance } => {
+                literal_freqs[257 + find_code(&LENGTH_BASE, length)] += 1;
+                distance_freqs[find_code(&DISTANCE_BASE, distance)] += 1;
+            }
+        }
+    }
+    literal_freqs[END_OF_BLOCK] += 1;
+
+    let dynamic = DynamicCodes::new(&literal_freqs, &distance_freqs);
+    let fixed_literals = Encoder::new_lsb_first(&fixed_literal_lengths());
+    let fixed_distances = Encoder::new_lsb_first(&fixed_distance_lengths());
+    let dynamic_bits = dynamic.header_bits()
+        + data_bits(
+            &literal_freqs,
+            &distance_freqs,
+            &dynamic.literals,
+            &dynamic.distances,
+        );
+    let fixed_bits = data_bits(
+        &literal_freqs,
+        &distance_freqs,
+        &fixed_literals,
+        &fixed_distances,
+    );
+
+    let count = cmp::max(1, raw.len().div_ceil(MAX_STORED));
+    let stored_bits = 8 * (raw.len() + 5 * count) + 7;
+
+    if stored_bits < cmp::min(dynamic_bits, fixed_bits) {
+        for i in 0..count {
+            let chunk = &raw[i * MAX_STORED..cmp::min(raw.len(), (i + 1) * MAX_STORED)];
+            write_stored(writer, chunk, last && i + 1 == count);
+        }
+    } else if fixed_bits <= dynamic_bits {
+        writer.write(last as u32, 1);
+        writer.write(FIXED, 2);
+        write_tokens(writer, tokens, &fixed_literals, &fixed_distances);
+    } else {
+        writer.write(last as u32, 1);
+        writer.write(DYNAMIC, 2);
+        dynamic.write_header(writer);
+        write_tokens(writer, tokens, &dynamic.literals, &dynamic.distances);
+    }
+}
+
+fn write_stored(writer: &mut LsbBitWriter, data: &[u8], last: bool) {
+    writer.write(last as u32, 1);
+    writer.write(STORED, 2);
+    writer.align();
+    let len = data.len() as u16;
+    writer.write_bytes(&len.to_le_bytes());
+    writer.write_bytes(&(!len).to_le_bytes());
+    writer.write_bytes(data);
+}
+
+/// Writes the blocks of `data[start..]`, matches may refer to the data before it
+fn write_blocks(
+    writer: &mut LsbBitWriter,
+    data: &[u8],
+    start: usize,
+    params: &Deflate,
+    last: bool,
+) {
+    let tokens = matcher::parse_from(
+        data,
+        start,
+        WINDOW_SIZE,
+        MAX_MATCH,
+        cmp::max(params.max_chain, 1) as usize,
+        params.lazy,
+    );
+    if tokens.is_empty() && last {
+        write_block(writer, &[], &[], true);
+    }
+
+    let mut position = start;
+    let mut blocks = tokens.chunks(BLOCK_TOKENS).peekable();
+    while let Some(block) = blocks.next() {
+        let len: usize = block
+            .iter()
+            .map(|token| match token {
+                Token::Literal(_) => 1,
+                Token::Match { length, .. } => *length,
+            })
+            .sum();
+        let raw = &data[position..position + len];
+        write_block(writer, block, raw, last && blocks.peek().is_none());
+        position += len;
+    }
+}
+
+/// Appends the deflate stream of `data` to `buffer`
+pub fn compress(data: &[u8], params: &Deflate, buffer: Vec<u8>) -> Vec<u8> {
+    let mut writer = LsbBitWriter::new(buffer);
+    write_blocks(&mut writer, data, 0, params, true);
+    writer.finish()
+}
+
+/// Compresses everything from reader into writer in segments, keeping the window of the
+/// previous segment for matches. Returns the number of bytes written.
+pub fn compress_stream(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    params: &Deflate,
+) -> Result<u64> {
+    let mut data = Vec::with_capacity(WINDOW_SIZE + SEGMENT_SIZE);
+    let mut bits = LsbBitWriter::new(Vec::new());
+    let mut written = 0;
+    loop {
+        let window = data.len().saturating_sub(WINDOW_SIZE);
+        data.drain(..window);
+        let start = data.len();
+        let len = reader.take(SEGMENT_SIZE as u64).read_to_end(&mut data)?;
+        // A full segment may be followed by an empty last one
+        let last = len < SEGMENT_SIZE;
+        write_blocks(&mut bits, &data, start, params, last);
+
+        if last {
+            bits.align();
+        }
+        let bytes = bits.take_bytes();
+        writer.write_all(&bytes)?;
+        written += bytes.len() as u64;
+        if last {
+            writer.flush()?;
+            return Ok(written);
+        }
+    }
+}
+
+/// Decoded data, of which all but the window is passed on once the buffer limit is reached
+struct Output<'a> {
+    decoded: Vec<u8>,
+    writer: Option<&'a mut dyn Write>,
+    limit: usize,
+    written: u64,
+}
+
+impl<'a> Output<'a> {
+    /// Keeps all data in the buffer
+    fn buffered(capacity: usize) -> Self {
+        Output {
+            decoded: Vec::with_capacity(capacity),
+            writer: None,
+            limit: usize::MAX,
+            written: 0,
+        }
+    }
+
+    fn streamed(writer: &'a mut dyn Write) -> Self {
+        Output {
+            decoded: Vec::with_capacity(WINDOW_SIZE + OUTPUT_SIZE + MAX_STORED),
+            writer: Some(writer),
+            limit: WINDOW_SIZE + OUTPUT_SIZE,
+            written: 0,
+        }
+    }
+
+    /// Writes out all data but the window if the buffer limit is reached
+    #[inline]
+    fn check_limit(&mut self) -> Result<()> {
+        if self.decoded.len() >= self.limit {
+            self.write_out(self.decoded.len() - WINDOW_SIZE)?;
+        }
+        Ok(())
+    }
+
+    fn write_out(&mut self, len: usize) -> Result<()> {
+        if let Some(writer) = self.writer.as_mut() {
+            writer.write_all(&self.decoded[..len])?;
+            self.decoded.drain(..len);
+            self.written += len as u64;
+        }
+        Ok(())
+    }
+}
+
+/// Restores the data of a deflate stream, returns it with the length of the stream
+pub fn decompress(data: &[u8]) -> Result<(Vec<u8>, usize)> {
+    let mut reader = LsbBitReader::new(data);
+    let mut output = Output::buffered(data.len() * 3);
+    inflate(&mut reader, &mut output)?;
+    reader.align()?;
+    Ok((output.decoded, reader.byte_position()))
+}
+
+/// Restores a deflate stream from reader into writer, keeping only a window of the
+/// decoded data. The reader is left aligned after the stream, returns the number of
+/// bytes written.
+pub fn decompress_stream(reader: &mut LsbStreamReader, writer: &mut dyn Write) -> Result<u64> {
+    let mut output = Output::streamed(writer);
+    inflate(reader, &mut output)?;
+    reader.align()?;
+    output.write_out(output.decoded.len())?;
+    if let Some(writer) = output.writer {
+        writer.flush()?;
+    }
+    Ok(output.written)
+}
+
+fn inflate<R: BitSource>(reader: &mut R, output: &mut Output) -> Result<()> {
+    let mut fixed: Option<(Decoder, Decoder)> = None;
+
+    loop {
+        output.check_limit()?;
+        let last = reader.read(1)? == 1;
+        match reader.read(2)? {
+            STORED => {
+                reader.align()?;
+                let len = reader.read(16)?;
+                if len != !reader.read(16)? & 0xffff {
+                    return Err(ComprsError::corrupt("invalid stored block length"));
+                }
+                for _ in 0..len {
+                    output.decoded.push(reader.read(8)? as u8);
+                }
+            }
+            FIXED => {
+                if fixed.is_none() {
+                    fixed = Some((
+                        Decoder::new_lsb_first(&fixed_literal_lengths())?,
+                        Decoder::new_lsb_first(&fixed_distance_lengths())?,
+                    ));
+                }
+                let (literals, distances) = fixed.as_ref().unwrap();
+                read_tokens(reader, literals, distances, output)?;
+            }
+            DYNAMIC => {
+                let (literals, distances) = read_dynamic_header(reader)?;
+                read_tokens(reader, &literals, &distances, output)?;
+            }
+            _ => return Err(ComprsError::corrupt("invalid deflate block type")),
+        }
+        if last {
+            return Ok(());
+        }
+    }
+}
+
+fn read_dynamic_header<R: BitSource>(reader: &mut R) -> Result<(Decoder, Decoder)> {
+    let num_literals = reader.read(5)? as usize + 257;
+    let num_distances = reader.read(5)? as usize + 1;
+    let num_code_lengths = reader.read(4)? as usize + 4;
+    if num_literals > NUM_LITERAL_CODES || num_distances > NUM_DISTANCE_CODES {
+        return Err(ComprsError::corrupt("too many deflate codes"));
+    }
+
+    let mut code_length_lengths = [0u8; NUM_CODE_LENGTH_CODES];
+    for symbol in &CODE_LENGTH_ORDER[..num_code_lengths] {
+        code_length_lengths[*symbol] = reader.read(3)? as u8;
+    }
+    let code_lengths = Decoder::new_lsb_first(&code_length_lengths)?;
+
+    let total = num_literals + num_distances;
+    let mut lengths = Vec::with_capacity(total);
+    while lengths.len() < total {
+        let (len, repeat) = match code_lengths.read(reader)? {
+            16 => match lengths.last() {
+                Some(len) => (*len, 3 + reader.read(2)?),
+                None => {
+                    return Err(ComprsError::corrupt(
+                        "repeated code length without a length",
+                    ))
+                }
+            },
+            17 => (0, 3 + reader.read(3)?),
+            18 => (0, 11 + reader.read(7)?),
+            len => (len as u8, 1),
+        };
+        if lengths.len() + repeat as usize > total {
+            return Err(ComprsError::corrupt("too many deflate code lengths"));
+        }
+        lengths.extend((0..repeat).map(|_| len));
+    }
+    if lengths[END_OF_BLOCK] == 0 {
+        return Err(ComprsError::corrupt("missing end of block code"));
+    }
+
+    Ok((
+        Decoder::new_lsb_first(&lengths[..num_literals])?,
+        Decoder::new_lsb_first(&lengths[num_literals..])?,
+    ))
+}
+
+fn read_tokens<R: BitSource>(
+    reader: &mut R,
+    literals: &Decoder,
+    distances: &Decoder,
+    output: &mut Output,
+) -> Result<()> {
+    loop {
+        output.check_limit()?;
+        let decoded = &mut output.decoded;
+        let symbol = literals.read(reader)?;
+        if symbol < END_OF_BLOCK {
+            decoded.push(symbol as u8);
+            continue;
+        }
+        if symbol == END_OF_BLOCK {
+            return Ok(());
+        }
+
+        let code = symbol - 257;
+        if code >= LENGTH_BASE.len() {
+            return Err(ComprsError::corrupt("invalid deflate length code"));
+        }
+        let length =
+            LENGTH_BASE[code] as usize + reader.read(u32::from(LENGTH_EXTRA[code]))? as usize;
+        let code = distances.read(reader)?;
+        if code >= NUM_DISTANCE_CODES {
+            return Err(ComprsError::corrupt("invalid deflate distance code"));
+        }
+        let distance =
+            DISTANCE_BASE[code] as usize + reader.read(u32::from(DISTANCE_EXTRA[code]))? as usize;
+        if distance > decoded.len() {
+            return Err(ComprsError::corrupt("deflate match before start of data"));
+        }
+
+        let start = decoded.len() - distance;
+        if distance >= length {
+            decoded.extend_from_within(start..start + length);
+        } else {
+            for i in start..start + length {
+                decoded.push(decoded[i]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codec::Codec;
+    use crate::utils::random_bytes;
+
+    use super::{compress, decompress, Deflate, OUTPUT_SIZE, SEGMENT_SIZE};
+
+    #[test]
+    fn test_decode_zlib() {
+        // Streams written by zlib with a fixed, a dynamic and a stored block
+        let fixed = [0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x90, 0x00];
+        assert_eq!(
+            Deflate::default().decode(&fixed).unwrap(),
+            b"hello hello hello"
+        );
+
+        let dynamic = [
+            0xb5, 0xcb, 0xc7, 0x01, 0x80, 0x20, 0x10, 0x05, 0xd1, 0x56, 0x7e, 0x05, 0xd4, 0xe2,
+            0xc1, 0x06, 0x40, 0x49, 0x06, 0x56, 0xb2, 0x50, 0xbd, 0xdb, 0x84, 0xe7, 0x79, 0xb3,
+            0x3a, 0x8d, 0x58, 0xfd, 0x76, 0x42, 0x25, 0xea, 0x01, 0x86, 0x5e, 0x1c, 0xf5, 0x7e,
+            0x32, 0xa8, 0xe9, 0x84, 0xc2, 0xf9, 0x92, 0x73, 0x60, 0x27, 0x2b, 0xb0, 0xfe, 0x86,
+            0x17, 0xc9, 0xee, 0x1e, 0x50, 0x8c, 0xba, 0x2f, 0x0e, 0xc6, 0x37, 0xcd, 0x69, 0xea,
+            0x80, 0xcb, 0xc7, 0x4a, 0x89, 0x5f, 0x9b, 0xc5, 0x07,
+        ];
+        let mut text = "The quick brown fox jumps over the lazy dog. ".repeat(3);
+        text.push_str("Pack my box with five dozen liquor jugs.");
+        assert_eq!(
+            Deflate::default().decode(&dynamic).unwrap(),
+            text.as_bytes()
+        );
+
+        let stored = [
+            0x01, 0x06, 0x00, 0xf9, 0xff, 0x73, 0x74, 0x6f, 0x72, 0x65, 0x64,
+        ];
+        assert_eq!(Deflate::default().decode(&stored).unwrap(), b"stored");
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let text = "Lorem ipsum dolor sit amet, consectetur adipiscing elit. ".repeat(100);
+        let mut repeated = random_bytes(20_000);
+        repeated.extend_from_within(..20_000);
+        let inputs: Vec<Vec<u8>> = vec![
+            Vec::new(),
+            vec![42],
+            vec![0; 300_000],
+            text.into_bytes(),
+            random_bytes(150_000),
+            repeated,
+        ];
+        for level in &[0, 3, 6] {
+            let params = Deflate::from_level(*level);
+            for data in &inputs {
+                let encoded = params.encode(data).unwrap();
+                assert_eq!(&params.decode(&encoded).unwrap(), data);
+                // Incompressible data is stored with little overhead
+                assert!(encoded.len() <= data.len() + data.len() / 1000 + 8);
+            }
+        }
+    }
+
+    #[test]
+    fn test_stream() {
+        // Matches reach into the previous segment, the zeros are decoded in long
+        // matches which fill the output buffer within a block
+        let mut data = random_bytes(SEGMENT_SIZE);
+        data.extend_from_within(SEGMENT_SIZE - 1000..);
+        data.extend_from_slice(&vec![0; 2 * OUTPUT_SIZE]);
+        // The last segment is empty
+        let full = random_bytes(SEGMENT_SIZE);
+
+        let params = Deflate::from_level(0);
+        for data in &[data, full] {
+            let mut streamed = Vec::new();
+            let len = params
+                .encode_stream(&mut data.as_slice(), &mut streamed)
+                .unwrap();
+            assert_eq!(len as usize, streamed.len());
+            assert_eq!(&params.decode(&streamed).unwrap(), data);
+
+            let mut restored = Vec::new();
+            let encoded = params.encode(data).unwrap();
+            params
+                .decode_stream(&mut encoded.as_slice(), &mut restored)
+                .unwrap();
+            assert_eq!(&restored, data);
+        }
+
+        let mut encoded = params.encode(b"trailing").unwrap();
+        encoded.push(0);
+        assert!(params
+            .decode_stream(&mut encoded.as_slice(), &mut Vec::new())
+            .is_err());
+    }
+
+    #[test]
+    fn test_stream_length() {
+        let data = b"abcabcabc".repeat(10);
+        let mut encoded = compress(&data, &Deflate::default(), vec![1, 2, 3]);
+        let len = encoded.len() - 3;
+        encoded.extend_from_slice(b"trailer");
+        let (decoded, consumed) = decompress(&encoded[3..]).unwrap();
+        assert_eq!(decoded, data);
+        assert_eq!(consumed, len);
+    }
+
+    #[test]
+    fn test_corrupt() {
+        // Reserved block type
+        assert!(decompress(&[0x07]).is_err());
+        // Stored length does not match its complement
+        assert!(decompress(&[0x01, 0x06, 0x00, 0xf9, 0xfe]).is_err());
+        // Match distance before the start of the data
+        assert!(decompress(&[0x03, 0x02, 0x00]).is_err());
+
+        let data = b"hello hello hello".repeat(20);
+        let encoded = Deflate::default().encode(&data).unwrap();
+        assert!(decompress(&encoded[..encoded.len() - 2]).is_err());
+    }
+}
@@ -0,0 +1,154 @@
+use std::io::{Read, Write};
+
+use crate::error::{ComprsError, Result};
+
+/// Signature at the start of every compressed stream
+pub const MAGIC: [u8; 4] = *b"CMPR";
+/// Current version of the container format
+pub const FORMAT_VERSION: u8 = 1;
+
+/// Length and checksum are unknown when the header is written and follow the last block instead
+pub const FLAG_STREAMED: u8 = 0b0000_0001;
+
+/// Container header, stored as:
+/// magic (4) | version (1) | flags (1) | original length (8, LE) | adler32 checksum (4, LE)
+#[derive(Debug, PartialEq)]
+pub struct Header {
+    pub version: u8,
+    pub flags: u8,
+    pub original_len: u64,
+    pub checksum: u32,
+}
+
+impl Header {
+    /// Creates a header for input of known length and checksum
+    pub fn new(original_len: u64, checksum: u32) -> Self {
+        Header {
+            version: FORMAT_VERSION,
+            flags: 0,
+            original_len,
+            checksum,
+        }
+    }
+
+    /// Creates a header whose length and checksum are written in a trailer
+    pub fn streamed() -> Self {
+        Header {
+            version: FORMAT_VERSION,
+            flags: FLAG_STREAMED,
+            original_len: 0,
+            checksum: 0,
+        }
+    }
+
+    #[inline]
+    pub fn is_streamed(&self) -> bool {
+        self.flags & FLAG_STREAMED != 0
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&[self.version, self.flags])?;
+        write_summary(writer, self.original_len, self.checksum)
+    }
+
+    /// Reads a header and verifies its signature and version
+    pub fn read<R: Read>(reader: &mut R) -> Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(ComprsError::corrupt("not a comprs stream"));
+        }
+
+        let mut buf = [0u8; 2];
+        reader.read_exact(&mut buf)?;
+        let (version, flags) = (buf[0], buf[1]);
+        if version != FORMAT_VERSION {
+            return Err(ComprsError::UnsupportedVersion(version));
+        }
+
+        let (original_len, checksum) = read_summary(reader)?;
+        Ok(Header {
+            version,
+            flags,
+            original_len,
+            checksum,
+        })
+    }
+
+    /// Compares length and checksum of the restored data against the stored values
+    pub fn verify(&self, original_len: u64, checksum: u32) -> Result<()> {
+        if self.original_len != original_len {
+            return Err(ComprsError::Corrupt(format!(
+                "length does not match: expected {}, got {}",
+                self.original_len, original_len
+            )));
+        }
+        if self.checksum != checksum {
+            return Err(ComprsError::ChecksumMismatch {
+                expected: self.checksum,
+                actual: checksum,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Writes original length and checksum, used by the header and the trailer of streamed data
+pub fn write_summary<W: Write>(writer: &mut W, original_len: u64, checksum: u32) -> Result<()> {
+    writer.write_all(&original_len.to_le_bytes())?;
+    writer.write_all(&checksum.to_le_bytes())?;
+    Ok(())
+}
+
+pub fn read_summary<R: Read>(reader: &mut R) -> Result<(u64, u32)> {
+    let mut len = [0u8; 8];
+    reader.read_exact(&mut len)?;
+    let mut checksum = [0u8; 4];
+    reader.read_exact(&mut checksum)?;
+    Ok((u64::from_le_bytes(len), u32::from_le_bytes(checksum)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{ComprsError, Result};
+
+    use super::{Header, FORMAT_VERSION};
+
+    #[test]
+    fn test_roundtrip() -> Result<()> {
+        let header = Header::new(1234, 0xdead_beef);
+        let mut buffer = Vec::new();
+        header.write(&mut buffer)?;
+        assert_eq!(buffer.len(), 18);
+        assert_eq!(Header::read(&mut buffer.as_slice())?, header);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid() -> Result<()> {
+        let mut buffer = Vec::new();
+        Header::streamed().write(&mut buffer)?;
+
+        let mut bad_magic = buffer.clone();
+        bad_magic[0] = b'X';
+        match Header::read(&mut bad_magic.as_slice()) {
+            Err(ComprsError::Corrupt(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        let mut bad_version = buffer.clone();
+        bad_version[4] = FORMAT_VERSION + 1;
+        match Header::read(&mut bad_version.as_slice()) {
+            Err(ComprsError::UnsupportedVersion(v)) => assert_eq!(v, FORMAT_VERSION + 1),
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        let truncated = &buffer[..10];
+        match Header::read(&mut &truncated[..]) {
+            Err(ComprsError::Io(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,360 @@
+use std::io::{self, BufRead, ErrorKind};
+use std::marker::PhantomData;
+use std::mem;
+
+use crate::error::{ComprsError, Result};
+
+/// Destination of bit fields
+pub trait BitSink {
+    /// Writes the lowest `len` bits of `value`, at most 32
+    fn write(&mut self, value: u32, len: u32);
+}
+
+/// Source of bit fields, see `BitSink`
+pub trait BitSource {
+    /// Returns the next `len` bits without consuming them, at most 32
+    fn peek(&mut self, len: u32) -> u32;
+    fn consume(&mut self, len: u32) -> Result<()>;
+    /// Skips the remaining bits of the current byte
+    fn align(&mut self) -> Result<()>;
+
+    #[inline]
+    fn read(&mut self, len: u32) -> Result<u32> {
+        let value = self.peek(len);
+        self.consume(len)?;
+        Ok(value)
+    }
+}
+
+/// Returns the lowest `len` bits of `value` in reverse order
+#[inline]
+pub fn reverse(value: u32, len: u32) -> u32 {
+    if len == 0 {
+        return 0;
+    }
+    value.reverse_bits() >> (32 - len)
+}
+
+/// Writes bits most significant first into a byte vector
+pub struct BitWriter {
+    buffer: Vec<u8>,
+    // Pending bits in the low `count` bits
+    bits: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    pub fn new(buffer: Vec<u8>) -> Self {
+        BitWriter {
+            buffer,
+            bits: 0,
+            count: 0,
+        }
+    }
+
+    /// Removes and returns the completed bytes, pending bits stay in the writer
+    pub fn take_bytes(&mut self) -> Vec<u8> {
+        mem::take(&mut self.buffer)
+    }
+
+    /// Pads the last byte with zeros and returns the written data
+    pub fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.buffer.push((self.bits << (8 - self.count)) as u8);
+        }
+        self.buffer
+    }
+}
+
+impl BitSink for BitWriter {
+    #[inline]
+    fn write(&mut self, value: u32, len: u32) {
+        debug_assert!(len <= 32);
+        self.bits = self.bits << len | u64::from(value) & ((1 << len) - 1);
+        self.count += len;
+        while self.count >= 8 {
+            self.count -= 8;
+            self.buffer.push((self.bits >> self.count) as u8);
+        }
+    }
+}
+
+/// Writes bits least significant first into a byte vector, as used by deflate
+pub struct LsbBitWriter {
+    buffer: Vec<u8>,
+    // Pending bits, starting at the lowest one
+    bits: u64,
+    count: u32,
+}
+
+impl LsbBitWriter {
+    pub fn new(buffer: Vec<u8>) -> Self {
+        LsbBitWriter {
+            buffer,
+            bits: 0,
+            count: 0,
+        }
+    }
+
+    /// Pads the current byte with zeros
+    pub fn align(&mut self) {
+        if self.count > 0 {
+            self.buffer.push(self.bits as u8);
+            self.bits = 0;
+            self.count = 0;
+        }
+    }
+
+    /// Appends whole bytes, the writer has to be aligned
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        debug_assert_eq!(self.count, 0);
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Removes and returns the completed bytes, pending bits stay in the writer
+    pub fn take_bytes(&mut self) -> Vec<u8> {
+        mem::take(&mut self.buffer)
+    }
+
+    /// Pads the last byte with zeros and returns the written data
+    pub fn finish(mut self) -> Vec<u8> {
+        self.align();
+        self.buffer
+    }
+}
+
+impl BitSink for LsbBitWriter {
+    #[inline]
+    fn write(&mut self, value: u32, len: u32) {
+        debug_assert!(len <= 32);
+        self.bits |= (u64::from(value) & ((1 << len) - 1)) << self.count;
+        self.count += len;
+        while self.count >= 8 {
+            self.buffer.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+}
+
+/// Order in which a reader takes the bits of each byte
+pub trait BitOrder {
+    /// Appends a byte to the `count` buffered bits
+    fn push(bits: u64, count: u32, byte: u8) -> u64;
+    /// Returns the next `len` of the `count` buffered bits
+    fn peek(bits: u64, count: u32, len: u32) -> u32;
+    /// Drops the next `len` buffered bits
+    fn consume(bits: u64, len: u32) -> u64;
+}
+
+/// Most significant bit first, as used by bzip2
+pub struct Msb;
+
+impl BitOrder for Msb {
+    #[inline]
+    fn push(bits: u64, _count: u32, byte: u8) -> u64 {
+        bits << 8 | u64::from(byte)
+    }
+
+    #[inline]
+    fn peek(bits: u64, count: u32, len: u32) -> u32 {
+        ((bits >> (count - len)) & ((1 << len) - 1)) as u32
+    }
+
+    #[inline]
+    fn consume(bits: u64, _len: u32) -> u64 {
+        bits
+    }
+}
+
+/// Least significant bit first, as used by deflate
+pub struct Lsb;
+
+impl BitOrder for Lsb {
+    #[inline]
+    fn push(bits: u64, count: u32, byte: u8) -> u64 {
+        bits | u64::from(byte) << count
+    }
+
+    #[inline]
+    fn peek(bits: u64, _count: u32, len: u32) -> u32 {
+        (bits & ((1 << len) - 1)) as u32
+    }
+
+    #[inline]
+    fn consume(bits: u64, len: u32) -> u64 {
+        bits >> len
+    }
+}
+
+/// Bytes taken by a bit reader one at a time
+pub trait ByteSource {
+    /// Returns the next byte, `None` at the end of the data
+    fn next(&mut self) -> Option<u8>;
+    /// Returns the error for consuming padding bits
+    fn truncated(&mut self) -> ComprsError;
+    /// Takes the error which ended the data early, if any
+    fn take_error(&mut self) -> Option<io::Error>;
+}
+
+/// Bytes of a slice
+pub struct SliceSource<'a> {
+    data: &'a [u8],
+    // Also counts the bytes taken past the end
+    position: usize,
+}
+
+impl<'a> ByteSource for SliceSource<'a> {
+    #[inline]
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.data.get(self.position).copied();
+        self.position += 1;
+        byte
+    }
+
+    fn truncated(&mut self) -> ComprsError {
+        ComprsError::corrupt("truncated bit stream")
+    }
+
+    fn take_error(&mut self) -> Option<io::Error> {
+        None
+    }
+}
+
+/// Bytes of a buffered reader, which are only taken as far as bits are peeked
+pub struct StreamSource<'a> {
+    reader: &'a mut dyn BufRead,
+    // Ends the data like its end does, reported once padding is consumed
+    error: Option<io::Error>,
+}
+
+impl<'a> ByteSource for StreamSource<'a> {
+    fn next(&mut self) -> Option<u8> {
+        while self.error.is_none() {
+            match self.reader.fill_buf() {
+                Ok(buffer) => {
+                    let byte = *buffer.first()?;
+                    self.reader.consume(1);
+                    return Some(byte);
+                }
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => self.error = Some(e),
+            }
+        }
+        None
+    }
+
+    fn truncated(&mut self) -> ComprsError {
+        match self.error.take() {
+            Some(e) => e.into(),
+            None => ComprsError::corrupt("truncated bit stream"),
+        }
+    }
+
+    fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+}
+
+/// Reads bits from a byte source in the given bit order. Reading past the end yields zeros
+/// until more bits were consumed than available, which is reported as truncated data.
+pub struct Reader<S: ByteSource, O: BitOrder> {
+    source: S,
+    bits: u64,
+    count: u32,
+    // Zero bits appended after the end of the data
+    padding: u32,
+    order: PhantomData<O>,
+}
+
+/// Reads bits most significant first from a slice
+pub type BitReader<'a> = Reader<SliceSource<'a>, Msb>;
+/// Reads bits least significant first from a slice
+pub type LsbBitReader<'a> = Reader<SliceSource<'a>, Lsb>;
+/// Reads bits most significant first from a buffered reader. Once all peeked bits are
+/// consumed, the reader is positioned right after them.
+pub type StreamReader<'a> = Reader<StreamSource<'a>, Msb>;
+/// Reads bits least significant first from a buffered reader, see `StreamReader`
+pub type LsbStreamReader<'a> = Reader<StreamSource<'a>, Lsb>;
+
+impl<S: ByteSource, O: BitOrder> Reader<S, O> {
+    fn with_source(source: S) -> Self {
+        Reader {
+            source,
+            bits: 0,
+            count: 0,
+            padding: 0,
+            order: PhantomData,
+        }
+    }
+
+    #[inline]
+    fn refill(&mut self, len: u32) {
+        while self.count < len {
+            let byte = self.source.next().unwrap_or_else(|| {
+                self.padding += 8;
+                0
+            });
+            self.bits = O::push(self.bits, self.count, byte);
+            self.count += 8;
+        }
+    }
+
+    /// Returns whether all data was consumed, the reader has to be aligned
+    pub fn is_at_end(&mut self) -> Result<bool> {
+        self.peek(8);
+        match self.source.take_error() {
+            Some(e) => Err(e.into()),
+            None => Ok(self.padding >= self.count),
+        }
+    }
+}
+
+impl<'a, O: BitOrder> Reader<SliceSource<'a>, O> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Reader::with_source(SliceSource { data, position: 0 })
+    }
+
+    /// Returns the number of bytes read so far, the reader has to be aligned
+    pub fn byte_position(&self) -> usize {
+        debug_assert_eq!(self.count % 8, 0);
+        self.source.position - (self.count / 8) as usize
+    }
+}
+
+impl<'a, O: BitOrder> Reader<StreamSource<'a>, O> {
+    pub fn new(reader: &'a mut dyn BufRead) -> Self {
+        Reader::with_source(StreamSource {
+            reader,
+            error: None,
+        })
+    }
+}
+
+impl<S: ByteSource, O: BitOrder> BitSource for Reader<S, O> {
+    #[inline]
+    fn peek(&mut self, len: u32) -> u32 {
+        debug_assert!(len <= 32);
+        if self.count < len {
+            self.refill(len);
+        }
+        O::peek(self.bits, self.count, len)
+    }
+
+    #[inline]
+    fn consume(&mut self, len: u32) -> Result<()> {
+        debug_assert!(len <= self.count);
+        self.bits = O::consume(self.bits, len);
+        self.count -= len;
+        if self.padding > self.count {
+            return Err(self.source.truncated());
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn align(&mut self) -> Result<()> {
+        let len = self.count % 8;
+        self.consume(len)
+    }
+}
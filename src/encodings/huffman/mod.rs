@@ -0,0 +1,489 @@
+use std::cmp;
+
+use crate::codec::Codec;
+use crate::error::{ComprsError, Result};
+
+use super::MAX_CAPACITY;
+
+use self::bits::{reverse, BitReader, BitSink, BitSource, BitWriter};
+
+pub mod bits;
+
+/// Longest code of the Huffman stage
+pub const MAX_CODE_LEN: u32 = 15;
+/// Codes up to this length are decoded with a single table lookup
+const LOOKUP_BITS: u32 = 10;
+/// Bits used for the first entry of a code length header
+const LENGTH_BITS: u32 = 5;
+const NUM_SYMBOLS: usize = 256;
+
+/// Order-0 canonical Huffman coder for bytes
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Huffman;
+
+impl Codec for Huffman {
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        apply(data)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        reduce(data)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Node {
+    Leaf(usize),
+    /// Items 2 i and 2 i + 1 of the previous level
+    Package(usize),
+}
+
+/// Returns optimal code lengths for the given frequencies, none longer than `max_len`.
+/// Symbols with frequency zero get length zero, a single used symbol gets length one.
+///
+/// Uses the package-merge algorithm of Larmore and Hirschberg.
+pub fn code_lengths(freqs: &[usize], max_len: u32) -> Vec<u8> {
+    let mut lengths = vec![0; freqs.len()];
+    let mut leaves: Vec<(usize, Node)> = freqs
+        .iter()
+        .enumerate()
+        .filter(|(_, freq)| **freq > 0)
+        .map(|(symbol, freq)| (*freq, Node::Leaf(symbol)))
+        .collect();
+    match leaves.len() {
+        0 => return lengths,
+        1 => {
+            if let Node::Leaf(symbol) = leaves[0].1 {
+                lengths[symbol] = 1;
+            }
+            return lengths;
+        }
+        n => assert!(n <= 1 << max_len, "too many symbols for the code length"),
+    }
+    leaves.sort_by_key(|(freq, _)| *freq);
+
+    // Each level merges the leaves with the pairs of items of the level before
+    let mut levels = vec![leaves.clone()];
+    for _ in 1..max_len {
+        let previous = levels.last().unwrap();
+        let packages = previous
+            .chunks_exact(2)
+            .enumerate()
+            .map(|(i, pair)| (pair[0].0 + pair[1].0, Node::Package(i)));
+        let mut level = Vec::with_capacity(leaves.len() + previous.len() / 2);
+        let mut leaves = leaves.iter().copied().peekable();
+        for package in packages {
+            while let Some(leaf) = leaves.next_if(|leaf| leaf.0 <= package.0) {
+                level.push(leaf);
+            }
+            level.push(package);
+        }
+        level.extend(leaves);
+        levels.push(level);
+    }
+
+    // A symbol is as long as the number of selected items it is part of
+    let mut stack: Vec<(usize, usize)> = (0..2 * leaves.len() - 2)
+        .map(|i| (levels.len() - 1, i))
+        .collect();
+    while let Some((level, index)) = stack.pop() {
+        match levels[level][index].1 {
+            Node::Leaf(symbol) => lengths[symbol] += 1,
+            Node::Package(i) => {
+                stack.push((level - 1, 2 * i));
+                stack.push((level - 1, 2 * i + 1));
+            }
+        }
+    }
+    lengths
+}
+
+/// Assigns canonical codes to the given code lengths: shorter codes come first and
+/// codes of the same length are ordered by symbol
+pub fn canonical_codes(lengths: &[u8]) -> Vec<u32> {
+    let max_len = lengths.iter().copied().max().unwrap_or(0) as usize;
+    let mut counts = vec![0u32; max_len + 1];
+    for &len in lengths {
+        counts[len as usize] += 1;
+    }
+    counts[0] = 0;
+
+    let mut next = vec![0u32; max_len + 1];
+    let mut code = 0;
+    for len in 1..=max_len {
+        code = (code + counts[len - 1]) << 1;
+        next[len] = code;
+    }
+    lengths
+        .iter()
+        .map(|&len| {
+            if len == 0 {
+                return 0;
+            }
+            let code = next[len as usize];
+            next[len as usize] += 1;
+            code
+        })
+        .collect()
+}
+
+/// Writes code lengths as in bzip2: the first length followed by the difference of
+/// each length to the one before, in steps of one
+pub fn write_lengths<W: BitSink>(writer: &mut W, lengths: &[u8]) {
+    let mut current = u32::from(lengths.first().copied().unwrap_or(0));
+    writer.write(current, LENGTH_BITS);
+    for &len in lengths {
+        let len = u32::from(len);
+        while current != len {
+            if current < len {
+                writer.write(0b10, 2);
+                current += 1;
+            } else {
+                writer.write(0b11, 2);
+                current -= 1;
+            }
+        }
+        writer.write(0, 1);
+    }
+}
+
+/// Reads `num_symbols` code lengths written by `write_lengths`, which have to be between
+/// `min_len` and `max_len`
+pub fn read_lengths<R: BitSource>(
+    reader: &mut R,
+    num_symbols: usize,
+    min_len: u32,
+    max_len: u32,
+) -> Result<Vec<u8>> {
+    let mut current = reader.read(LENGTH_BITS)?;
+    let mut lengths = Vec::with_capacity(num_symbols);
+    for _ in 0..num_symbols {
+        while reader.read(1)? == 1 {
+            if reader.read(1)? == 0 {
+                current += 1;
+            } else {
+                current = current.wrapping_sub(1);
+            }
+            if current > max_len {
+                return Err(ComprsError::corrupt("invalid Huffman code length"));
+            }
+        }
+        if current < min_len || current > max_len {
+            return Err(ComprsError::corrupt("invalid Huffman code length"));
+        }
+        lengths.push(current as u8);
+    }
+    Ok(lengths)
+}
+
+/// Writes symbols with the canonical codes of the given lengths
+pub struct Encoder {
+    codes: Vec<u32>,
+    lengths: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn new(lengths: &[u8]) -> Self {
+        Encoder {
+            codes: canonical_codes(lengths),
+            lengths: lengths.to_vec(),
+        }
+    }
+
+    /// Creates an encoder for writers which store bits least significant first.
+    /// Codes are still written starting with their most significant bit.
+    pub fn new_lsb_first(lengths: &[u8]) -> Self {
+        let codes = canonical_codes(lengths)
+            .iter()
+            .zip(lengths)
+            .map(|(code, len)| reverse(*code, u32::from(*len)))
+            .collect();
+        Encoder {
+            codes,
+            lengths: lengths.to_vec(),
+        }
+    }
+
+    /// Returns the code length of a symbol
+    #[inline]
+    pub fn code_len(&self, symbol: usize) -> u32 {
+        u32::from(self.lengths[symbol])
+    }
+
+    #[inline]
+    pub fn write<W: BitSink>(&self, writer: &mut W, symbol: usize) {
+        debug_assert!(self.lengths[symbol] > 0, "symbol has no code");
+        writer.write(self.codes[symbol], u32::from(self.lengths[symbol]));
+    }
+}
+
+/// Table driven decoder for canonical codes
+pub struct Decoder {
+    /// Symbol and length of the codes which fit into `lookup_bits`, indexed by the next
+    /// bits of the input. Zero marks longer or unused codes.
+    lookup: Vec<u32>,
+    lookup_bits: u32,
+    max_len: u32,
+    /// First code, number of codes and index of the first symbol of each length
+    first: Vec<u32>,
+    counts: Vec<u32>,
+    offsets: Vec<u32>,
+    /// Symbols ordered by their codes
+    symbols: Vec<u16>,
+    lsb_first: bool,
+}
+
+impl Decoder {
+    /// Builds the tables, fails if the lengths do not form a prefix code
+    pub fn new(lengths: &[u8]) -> Result<Self> {
+        Decoder::build(lengths, false)
+    }
+
+    /// Builds a decoder for readers which return bits least significant first
+    pub fn new_lsb_first(lengths: &[u8]) -> Result<Self> {
+        Decoder::build(lengths, true)
+    }
+
+    fn build(lengths: &[u8], lsb_first: bool) -> Result<Self> {
+        let max_len = u32::from(lengths.iter().copied().max().unwrap_or(0));
+        let mut counts = vec![0u32; max_len as usize + 1];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // Kraft inequality, incomplete codes are allowed for a single symbol
+        let space: u64 = (1..=max_len)
+            .map(|len| u64::from(counts[len as usize]) << (max_len - len))
+            .sum();
+        if space > 1 << max_len {
+            return Err(ComprsError::corrupt("oversubscribed Huffman code"));
+        }
+
+        let mut first = vec![0u32; max_len as usize + 1];
+        let mut offsets = vec![0u32; max_len as usize + 1];
+        let mut code = 0;
+        let mut offset = 0;
+        for len in 1..=max_len as usize {
+            code = (code + counts[len - 1]) << 1;
+            first[len] = code;
+            offsets[len] = offset;
+            offset += counts[len];
+        }
+
+        let mut symbols = vec![0u16; offset as usize];
+        let mut next = offsets.clone();
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len > 0 {
+                symbols[next[len as usize] as usize] = symbol as u16;
+                next[len as usize] += 1;
+            }
+        }
+
+        let lookup_bits = cmp::min(max_len, LOOKUP_BITS);
+        let mut lookup = vec![0u32; 1 << lookup_bits];
+        let codes = canonical_codes(lengths);
+        for (symbol, &len) in lengths.iter().enumerate() {
+            let len = u32::from(len);
+            if len == 0 || len > lookup_bits {
+                continue;
+            }
+            let entry = (symbol as u32) << 5 | len;
+            if lsb_first {
+                // The code occupies the lowest bits of the index
+                let code = reverse(codes[symbol], len) as usize;
+                for index in (code..lookup.len()).step_by(1 << len) {
+                    lookup[index] = entry;
+                }
+            } else {
+                let start = (codes[symbol] << (lookup_bits - len)) as usize;
+                let end = start + (1 << (lookup_bits - len));
+                for slot in &mut lookup[start..end] {
+                    *slot = entry;
+                }
+            }
+        }
+
+        Ok(Decoder {
+            lookup,
+            lookup_bits,
+            max_len,
+            first,
+            counts,
+            offsets,
+            symbols,
+            lsb_first,
+        })
+    }
+
+    #[inline]
+    pub fn read<R: BitSource>(&self, reader: &mut R) -> Result<usize> {
+        let entry = self.lookup[reader.peek(self.lookup_bits) as usize];
+        if entry != 0 {
+            reader.consume(entry & 31)?;
+            return Ok((entry >> 5) as usize);
+        }
+        self.read_long(reader)
+    }
+
+    fn read_long<R: BitSource>(&self, reader: &mut R) -> Result<usize> {
+        for len in self.lookup_bits + 1..=self.max_len {
+            let mut code = reader.peek(len);
+            if self.lsb_first {
+                code = reverse(code, len);
+            }
+            let index = code.wrapping_sub(self.first[len as usize]);
+            if index < self.counts[len as usize] {
+                reader.consume(len)?;
+                let symbol = self.symbols[(self.offsets[len as usize] + index) as usize];
+                return Ok(symbol as usize);
+            }
+        }
+        Err(ComprsError::corrupt("invalid Huffman code"))
+    }
+}
+
+pub fn apply(data: &[u8]) -> Result<Vec<u8>> {
+    let mut buffer = Vec::with_capacity(data.len() / 2 + 16);
+    bincode::serialize_into(&mut buffer, &(data.len() as u64))?;
+    if data.is_empty() {
+        return Ok(buffer);
+    }
+
+    let mut freqs = vec![0; NUM_SYMBOLS];
+    for byte in data {
+        freqs[*byte as usize] += 1;
+    }
+    let lengths = code_lengths(&freqs, MAX_CODE_LEN);
+    let mut writer = BitWriter::new(buffer);
+    write_lengths(&mut writer, &lengths);
+
+    let encoder = Encoder::new(&lengths);
+    for byte in data {
+        encoder.write(&mut writer, *byte as usize);
+    }
+    Ok(writer.finish())
+}
+
+pub fn reduce(mut data: &[u8]) -> Result<Vec<u8>> {
+    let len: u64 = bincode::deserialize_from(&mut data)?;
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut reader = BitReader::new(data);
+    let lengths = read_lengths(&mut reader, NUM_SYMBOLS, 0, MAX_CODE_LEN)?;
+    let decoder = Decoder::new(&lengths)?;
+
+    let mut decoded = Vec::with_capacity(cmp::min(len as usize, MAX_CAPACITY));
+    for _ in 0..len {
+        decoded.push(decoder.read(&mut reader)? as u8);
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::encodings::mtf;
+    use crate::error::ComprsError;
+
+    use super::bits::{BitReader, BitSink, BitWriter};
+    use super::{
+        apply, canonical_codes, code_lengths, read_lengths, reduce, write_lengths, LENGTH_BITS,
+    };
+
+    fn kraft_sum(lengths: &[u8], max_len: u32) -> u64 {
+        lengths
+            .iter()
+            .filter(|len| **len > 0)
+            .map(|len| 1u64 << (max_len - u32::from(*len)))
+            .sum()
+    }
+
+    #[test]
+    fn test_code_lengths() {
+        assert_eq!(code_lengths(&[0, 0, 0], 15), vec![0, 0, 0]);
+        assert_eq!(code_lengths(&[0, 5, 0], 15), vec![0, 1, 0]);
+        assert_eq!(code_lengths(&[1, 1, 2, 4], 15), vec![3, 3, 2, 1]);
+
+        // Fibonacci frequencies give the deepest possible tree
+        let mut freqs = vec![1, 1];
+        while freqs.len() < 20 {
+            freqs.push(freqs[freqs.len() - 1] + freqs[freqs.len() - 2]);
+        }
+        assert_eq!(code_lengths(&freqs, 20).iter().max(), Some(&19));
+        for max_len in 5..=12 {
+            let lengths = code_lengths(&freqs, max_len);
+            assert_eq!(lengths.iter().copied().max(), Some(max_len as u8));
+            assert_eq!(kraft_sum(&lengths, max_len), 1 << max_len);
+        }
+        let lengths = code_lengths(&[1; 256], 8);
+        assert!(lengths.iter().all(|len| *len == 8));
+    }
+
+    #[test]
+    fn test_canonical_codes() {
+        assert_eq!(
+            canonical_codes(&[3, 3, 3, 3, 3, 2, 4, 4]),
+            vec![0b010, 0b011, 0b100, 0b101, 0b110, 0b00, 0b1110, 0b1111]
+        );
+    }
+
+    #[test]
+    fn test_lengths_header() {
+        let lengths: Vec<u8> = (0..=255u32).map(|i| (i % 16) as u8).collect();
+        let mut writer = BitWriter::new(Vec::new());
+        write_lengths(&mut writer, &lengths);
+        let data = writer.finish();
+        let mut reader = BitReader::new(&data);
+        assert_eq!(read_lengths(&mut reader, 256, 0, 15).unwrap(), lengths);
+        assert!(read_lengths(&mut BitReader::new(&data), 256, 0, 14).is_err());
+        assert!(read_lengths(&mut BitReader::new(&data), 256, 1, 15).is_err());
+
+        // The first length is checked even without any differences
+        let mut writer = BitWriter::new(Vec::new());
+        writer.write(31, LENGTH_BITS);
+        writer.write(0, 3);
+        let data = writer.finish();
+        assert!(read_lengths(&mut BitReader::new(&data), 3, 0, 20).is_err());
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let text = "Lorem ipsum dolor sit amet, consectetur adipiscing elit. ".repeat(40);
+        let mut skewed = vec![0; 100_000];
+        skewed.extend((0..=255).cycle().take(1000));
+        let inputs: Vec<Vec<u8>> = vec![
+            Vec::new(),
+            vec![42],
+            vec![7; 1000],
+            text.clone().into_bytes(),
+            mtf::apply(text.as_bytes()),
+            (0..=255).collect(),
+            skewed,
+        ];
+        for data in &inputs {
+            let encoded = apply(data).unwrap();
+            assert_eq!(&reduce(&encoded).unwrap(), data);
+            if data.len() > 1000 {
+                assert!(encoded.len() < data.len() * 3 / 4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let data = "abracadabra".repeat(10).into_bytes();
+        let encoded = apply(&data).unwrap();
+        match reduce(&encoded[..encoded.len() - 2]) {
+            Err(ComprsError::Corrupt(_)) => (),
+            result => panic!("unexpected result {:?}", result),
+        }
+        // Lengths of five for all symbols oversubscribe the code space
+        let mut writer = BitWriter::new(Vec::new());
+        write_lengths(&mut writer, &[5; 256]);
+        let mut corrupt = bincode::serialize(&1u64).unwrap();
+        corrupt.extend(writer.finish());
+        assert!(reduce(&corrupt).is_err());
+    }
+}
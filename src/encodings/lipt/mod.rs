@@ -0,0 +1,488 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::codec::Codec;
+use crate::error::{ComprsError, Result};
+
+// Length-index preserving transform, as described by Awan and Mukherjee in
+// "LIPT: A Lossless Text Transform to Improve Compression"
+
+/// Common English words ordered by frequency, one per line
+const ENGLISH: &str = include_str!("english.txt");
+
+const MIN_LENGTH: usize = 2;
+/// Longest word which has a length letter
+const MAX_LENGTH: usize = 52;
+/// Letters of the codewords, their position is the value they stand for
+const LETTERS: &[u8; 52] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const BASE: usize = 52;
+/// Words of each length which get a codeword with one, two or three index letters
+const MAX_INDEX: usize = BASE + BASE * BASE + BASE * BASE * BASE;
+
+/// Starts a codeword, followed by the length letter and the index letters
+const STAR: u8 = b'*';
+/// Precedes the codeword of a word with an upper case first letter
+const CAPITAL: u8 = b'~';
+/// Precedes the codeword of a word in upper case
+const UPPER: u8 = b'`';
+/// Precedes literal `STAR`, `CAPITAL`, `UPPER` and `ESCAPE` bytes of the input
+const ESCAPE: u8 = b'\\';
+/// Terminates each word of a stored dictionary
+const SEPARATOR: u8 = b'\n';
+/// Words which occur less often are not added to a per-file dictionary
+const MIN_COUNT: usize = 2;
+
+/// Source of the words which are replaced by codewords
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Dictionary {
+    /// Built-in list of common English words
+    #[default]
+    English,
+    /// Words occurring repeatedly in the input, stored in front of the content
+    File,
+}
+
+impl fmt::Display for Dictionary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let printable = match *self {
+            Dictionary::English => "english",
+            Dictionary::File => "file",
+        };
+        write!(f, "{}", printable)
+    }
+}
+
+impl FromStr for Dictionary {
+    type Err = ComprsError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "english" => Ok(Dictionary::English),
+            "file" => Ok(Dictionary::File),
+            _ => Err(ComprsError::InvalidTransform(format!(
+                "unknown LIPT dictionary '{}'",
+                s
+            ))),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct LiptData {
+    dictionary: Dictionary,
+    /// Words of a per-file dictionary, each followed by `SEPARATOR`
+    words: Vec<u8>,
+    content: Vec<u8>,
+}
+
+/// Length-index preserving transform, which replaces dictionary words with codewords
+/// made up of their length and their index among the words of that length
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Lipt {
+    pub dictionary: Dictionary,
+}
+
+impl Codec for Lipt {
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        apply(data, self.dictionary)
+    }
+
+    /// Restores data using the dictionary stored in the encoded data
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        reduce(data)
+    }
+}
+
+#[inline]
+fn is_letter(byte: u8) -> bool {
+    byte.is_ascii_alphabetic()
+}
+
+#[inline]
+fn letter_value(byte: u8) -> Option<usize> {
+    match byte {
+        b'a'..=b'z' => Some(usize::from(byte - b'a')),
+        b'A'..=b'Z' => Some(usize::from(byte - b'A') + 26),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Case {
+    Lower,
+    Capital,
+    Upper,
+}
+
+/// Returns the case of a word if it can be restored from its lower case form
+fn case(word: &[u8]) -> Option<Case> {
+    let (first, rest) = word.split_first()?;
+    if rest.iter().all(u8::is_ascii_lowercase) {
+        if first.is_ascii_lowercase() {
+            Some(Case::Lower)
+        } else {
+            Some(Case::Capital)
+        }
+    } else if !rest.is_empty() && word.iter().all(u8::is_ascii_uppercase) {
+        Some(Case::Upper)
+    } else {
+        None
+    }
+}
+
+/// Returns the codeword of the word with the given length and index
+fn codeword(len: usize, index: usize) -> Vec<u8> {
+    let (width, mut value) = if index < BASE {
+        (1, index)
+    } else if index < BASE + BASE * BASE {
+        (2, index - BASE)
+    } else {
+        (3, index - BASE - BASE * BASE)
+    };
+    let mut code = vec![STAR, LETTERS[len - 1]];
+    code.resize(2 + width, 0);
+    for byte in code[2..].iter_mut().rev() {
+        *byte = LETTERS[value % BASE];
+        value /= BASE;
+    }
+    code
+}
+
+/// Returns the length and index of the letters following `STAR` in a codeword
+fn parse_codeword(letters: &[u8]) -> Option<(usize, usize)> {
+    let (len, digits) = letters.split_first()?;
+    let offset = match digits.len() {
+        1 => 0,
+        2 => BASE,
+        3 => BASE + BASE * BASE,
+        _ => return None,
+    };
+    let mut value = 0;
+    for &digit in digits {
+        value = value * BASE + letter_value(digit)?;
+    }
+    Some((letter_value(*len)? + 1, offset + value))
+}
+
+/// Groups the words by length, keeping their order. Words without a codeword are
+/// skipped.
+fn group_words<'a, I: Iterator<Item = &'a [u8]>>(words: I) -> Vec<Vec<&'a [u8]>> {
+    let mut groups = vec![Vec::new(); MAX_LENGTH + 1];
+    for word in words {
+        let len = word.len();
+        if (MIN_LENGTH..=MAX_LENGTH).contains(&len) && groups[len].len() < MAX_INDEX {
+            groups[len].push(word);
+        }
+    }
+    groups
+}
+
+fn english_words() -> impl Iterator<Item = &'static [u8]> {
+    ENGLISH.lines().map(str::as_bytes)
+}
+
+/// Returns the words which occur repeatedly in data, the most frequent first
+fn file_words(data: &[u8]) -> Vec<Vec<u8>> {
+    let mut counts: HashMap<Vec<u8>, usize> = HashMap::new();
+    for word in data.split(|b| !is_letter(*b)) {
+        if case(word).is_some() && (MIN_LENGTH..=MAX_LENGTH).contains(&word.len()) {
+            *counts.entry(word.to_ascii_lowercase()).or_insert(0) += 1;
+        }
+    }
+    let mut words: Vec<(Vec<u8>, usize)> = counts
+        .into_iter()
+        .filter(|(_, count)| *count >= MIN_COUNT)
+        .collect();
+    words.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    words.into_iter().map(|(word, _)| word).collect()
+}
+
+pub fn apply(data: &[u8], dictionary: Dictionary) -> Result<Vec<u8>> {
+    let file_words = match dictionary {
+        Dictionary::English => Vec::new(),
+        Dictionary::File => file_words(data),
+    };
+    let groups = match dictionary {
+        Dictionary::English => group_words(english_words()),
+        Dictionary::File => group_words(file_words.iter().map(Vec::as_slice)),
+    };
+
+    let mut words = Vec::new();
+    if dictionary == Dictionary::File {
+        for word in groups.iter().flatten() {
+            words.extend_from_slice(word);
+            words.push(SEPARATOR);
+        }
+    }
+
+    let mut codewords: HashMap<&[u8], Vec<u8>> = HashMap::new();
+    for (len, group) in groups.iter().enumerate() {
+        for (index, word) in group.iter().enumerate() {
+            codewords.insert(word, codeword(len, index));
+        }
+    }
+    debug!("DEBUG:LIPT: {} words in the dictionary", codewords.len());
+
+    let mut content = Vec::with_capacity(data.len());
+    let mut position = 0;
+    while position < data.len() {
+        let byte = data[position];
+        if is_letter(byte) {
+            let len = data[position..]
+                .iter()
+                .take_while(|b| is_letter(**b))
+                .count();
+            let word = &data[position..position + len];
+            position += len;
+            let case = case(word);
+            let code = case.and_then(|_| codewords.get(word.to_ascii_lowercase().as_slice()));
+            match (case, code) {
+                (Some(case), Some(code)) => {
+                    match case {
+                        Case::Lower => {}
+                        Case::Capital => content.push(CAPITAL),
+                        Case::Upper => content.push(UPPER),
+                    }
+                    content.extend_from_slice(code);
+                }
+                _ => content.extend_from_slice(word),
+            }
+            continue;
+        }
+        if byte == STAR || byte == CAPITAL || byte == UPPER || byte == ESCAPE {
+            content.push(ESCAPE);
+        }
+        content.push(byte);
+        position += 1;
+    }
+
+    Ok(bincode::serialize(&LiptData {
+        dictionary,
+        words,
+        content,
+    })?)
+}
+
+pub fn reduce(data: &[u8]) -> Result<Vec<u8>> {
+    let data: LiptData = bincode::deserialize(data)?;
+    let groups = match data.dictionary {
+        Dictionary::English => group_words(english_words()),
+        Dictionary::File => {
+            let words = match data.words.split_last() {
+                Some((&SEPARATOR, words)) => words,
+                Some(_) => return Err(ComprsError::corrupt("unterminated LIPT dictionary")),
+                None => &[],
+            };
+            let mut groups = vec![Vec::new(); MAX_LENGTH + 1];
+            if !words.is_empty() {
+                for word in words.split(|b| *b == SEPARATOR) {
+                    let len = word.len();
+                    if !(MIN_LENGTH..=MAX_LENGTH).contains(&len)
+                        || !word.iter().all(u8::is_ascii_lowercase)
+                        || groups[len].len() >= MAX_INDEX
+                    {
+                        return Err(ComprsError::corrupt("invalid word in LIPT dictionary"));
+                    }
+                    groups[len].push(word);
+                }
+            }
+            groups
+        }
+    };
+
+    let content = data.content;
+    let mut decoded = Vec::with_capacity(content.len());
+    let mut position = 0;
+    while position < content.len() {
+        let byte = content[position];
+        position += 1;
+        let case = match byte {
+            ESCAPE => {
+                let escaped = content
+                    .get(position)
+                    .ok_or_else(|| ComprsError::corrupt("truncated LIPT escape"))?;
+                decoded.push(*escaped);
+                position += 1;
+                continue;
+            }
+            STAR => Case::Lower,
+            CAPITAL | UPPER => {
+                if content.get(position) != Some(&STAR) {
+                    return Err(ComprsError::corrupt("LIPT case flag without codeword"));
+                }
+                position += 1;
+                if byte == CAPITAL {
+                    Case::Capital
+                } else {
+                    Case::Upper
+                }
+            }
+            _ => {
+                decoded.push(byte);
+                continue;
+            }
+        };
+
+        let len = content[position..]
+            .iter()
+            .take_while(|b| is_letter(**b))
+            .count();
+        let word = parse_codeword(&content[position..position + len])
+            .and_then(|(len, index)| groups.get(len)?.get(index))
+            .ok_or_else(|| ComprsError::corrupt("unknown LIPT codeword"))?;
+        position += len;
+        let start = decoded.len();
+        decoded.extend_from_slice(word);
+        match case {
+            Case::Lower => {}
+            Case::Capital => decoded[start].make_ascii_uppercase(),
+            Case::Upper => decoded[start..].make_ascii_uppercase(),
+        }
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply, codeword, parse_codeword, reduce, Dictionary, LiptData};
+    use super::{BASE, ENGLISH, MAX_INDEX};
+
+    #[test]
+    fn test_codeword() {
+        assert_eq!(codeword(3, 0), b"*ca");
+        assert_eq!(codeword(3, 51), b"*cZ");
+        assert_eq!(codeword(4, 52), b"*daa");
+        assert_eq!(codeword(52, 52 + 52 * 52), b"*Zaaa");
+        for &(len, index) in &[
+            (2, 0),
+            (7, 51),
+            (7, 52),
+            (9, 2755),
+            (9, 2756),
+            (30, 140_000),
+        ] {
+            let code = codeword(len, index);
+            assert_eq!(parse_codeword(&code[1..]), Some((len, index)));
+        }
+        assert_eq!(parse_codeword(b"c"), None);
+        assert_eq!(parse_codeword(b"caaaa"), None);
+    }
+
+    #[test]
+    fn test_english() {
+        let words: Vec<&str> = ENGLISH.lines().collect();
+        assert!(words.len() >= 1000);
+        assert!(words
+            .iter()
+            .all(|word| word.len() >= 2 && word.bytes().all(|b| b.is_ascii_lowercase())));
+        let mut unique = words.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!(unique.len(), words.len());
+    }
+
+    #[test]
+    fn test_apply() {
+        let data = b"The cat and THE hat of Zorro and zorro".to_vec();
+        let encoded = apply(&data, Dictionary::English).unwrap();
+        let decoded: LiptData = bincode::deserialize(&encoded).unwrap();
+        assert!(decoded.words.is_empty());
+        assert_eq!(
+            decoded.content,
+            b"~*ca *caR *cb `*ca *cbm *ba Zorro *cb zorro"
+        );
+        assert_eq!(reduce(&encoded).unwrap(), data);
+
+        let encoded = apply(&data, Dictionary::File).unwrap();
+        let decoded: LiptData = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(decoded.words, b"and\nthe\nzorro\n");
+        assert_eq!(decoded.content, b"~*cb cat *ca `*cb hat of ~*ea *ca *ea");
+        assert_eq!(reduce(&encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let inputs: Vec<Vec<u8>> = vec![
+            Vec::new(),
+            b"a".to_vec(),
+            b"*stars* ~tilde~ `ticks` \\escapes\\ and *THE* ~The~ MiXeD case".to_vec(),
+            vec![0xff, b't', b'h', b'e', 0xc3, b'a', b'n', b'd', 0x80, b'*'],
+        ];
+        for input in &inputs {
+            for &dictionary in &[Dictionary::English, Dictionary::File] {
+                assert_eq!(&reduce(&apply(input, dictionary).unwrap()).unwrap(), input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_flags() {
+        // Literal flags are escaped, also right before and after codewords
+        let data = b"~the `The *THE the~ \\the".to_vec();
+        let encoded = apply(&data, Dictionary::English).unwrap();
+        let decoded: LiptData = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(decoded.content, b"\\~*ca \\`~*ca \\*`*ca *ca\\~ \\\\*ca");
+        assert_eq!(reduce(&encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn test_file_dictionary_limit() {
+        // Distinct four letter words in sorted order, a few more than get a codeword
+        let words: Vec<Vec<u8>> = (0..MAX_INDEX + 10)
+            .map(|i| {
+                let mut word = [b'a'; 4];
+                let mut value = i;
+                for byte in word.iter_mut().rev() {
+                    *byte += (value % 26) as u8;
+                    value /= 26;
+                }
+                word.to_vec()
+            })
+            .collect();
+        let text = words.join(&b' ');
+        let data = [&text[..], &text[..]].join(&b' ');
+        let encoded = apply(&data, Dictionary::File).unwrap();
+        let decoded: LiptData = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(decoded.words.len(), MAX_INDEX * 5);
+
+        // Codewords with three index letters start after the two letter ones
+        let tokens: Vec<&[u8]> = decoded.content.split(|b| *b == b' ').collect();
+        assert_eq!(tokens[BASE + BASE * BASE - 1], b"*dZZ");
+        assert_eq!(tokens[BASE + BASE * BASE], b"*daaa");
+        assert_eq!(tokens[MAX_INDEX - 1], b"*dZZZ");
+        // The words sorted last keep their letters
+        assert_eq!(tokens[MAX_INDEX], words[MAX_INDEX].as_slice());
+        assert_eq!(reduce(&encoded).unwrap(), data);
+
+        // A stored dictionary may not exceed the codewords either
+        let mut words = decoded.words.clone();
+        words.extend_from_slice(b"zzzz\n");
+        let data = LiptData {
+            dictionary: Dictionary::File,
+            words,
+            content: Vec::new(),
+        };
+        assert!(reduce(&bincode::serialize(&data).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let invalid = vec![
+            (Dictionary::File, b"the".to_vec(), b"*ca".to_vec()),
+            (Dictionary::File, b"Th3\n".to_vec(), b"*ca".to_vec()),
+            (Dictionary::File, b"the\n".to_vec(), b"*cb".to_vec()),
+            (Dictionary::English, Vec::new(), b"~the".to_vec()),
+            (Dictionary::English, Vec::new(), b"*".to_vec()),
+            (Dictionary::English, Vec::new(), b"end\\".to_vec()),
+        ];
+        for (dictionary, words, content) in invalid {
+            let data = LiptData {
+                dictionary,
+                words,
+                content,
+            };
+            assert!(reduce(&bincode::serialize(&data).unwrap()).is_err());
+        }
+    }
+}
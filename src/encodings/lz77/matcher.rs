@@ -0,0 +1,192 @@
+use std::cmp;
+
+/// Shortest match which is found
+pub const MIN_MATCH: usize = 3;
+/// Matches of the shortest length are dropped beyond this distance, as they cost more
+/// than the literals they replace
+const TOO_FAR: usize = 4096;
+/// Matches at least this long are taken without looking for a longer one at the next position
+const LAZY_LIMIT: usize = 32;
+const HASH_BITS: u32 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Token {
+    Literal(u8),
+    /// Copy of `length` bytes starting `distance` bytes back
+    Match {
+        length: usize,
+        distance: usize,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Match {
+    pub length: usize,
+    pub distance: usize,
+}
+
+/// Finds earlier occurrences of the data at a position through chains of positions with
+/// the same hash of their first `MIN_MATCH` bytes
+pub struct MatchFinder<'a> {
+    data: &'a [u8],
+    window_size: usize,
+    max_match: usize,
+    max_chain: usize,
+    /// Most recent position plus one of each hash, zero if there is none
+    head: Vec<u32>,
+    /// Previous position plus one with the same hash, indexed by position modulo its length
+    prev: Vec<u32>,
+}
+
+impl<'a> MatchFinder<'a> {
+    pub fn new(data: &'a [u8], window_size: usize, max_match: usize, max_chain: usize) -> Self {
+        debug_assert!(window_size.is_power_of_two() && max_match >= MIN_MATCH);
+        // Positions of short inputs never wrap around a smaller chain buffer
+        let chain_size = cmp::min(window_size, data.len().next_power_of_two());
+        MatchFinder {
+            data,
+            window_size,
+            max_match,
+            max_chain,
+            head: vec![0; 1 << HASH_BITS],
+            prev: vec![0; chain_size],
+        }
+    }
+
+    #[inline]
+    fn hash(&self, pos: usize) -> usize {
+        let bytes = u32::from(self.data[pos]) << 16
+            | u32::from(self.data[pos + 1]) << 8
+            | u32::from(self.data[pos + 2]);
+        (bytes.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+    }
+
+    /// Adds a position to the chains, it has to be called for positions in order
+    #[inline]
+    pub fn insert(&mut self, pos: usize) {
+        if pos + MIN_MATCH > self.data.len() {
+            return;
+        }
+        let hash = self.hash(pos);
+        let mask = self.prev.len() - 1;
+        self.prev[pos & mask] = self.head[hash];
+        self.head[hash] = pos as u32 + 1;
+    }
+
+    /// Returns the longest match at `pos` which is longer than `min_length`
+    pub fn find(&self, pos: usize, min_length: usize) -> Option<Match> {
+        let max_length = cmp::min(self.max_match, self.data.len() - pos);
+        if max_length < MIN_MATCH || min_length >= max_length {
+            return None;
+        }
+        let mask = self.prev.len() - 1;
+        let current = &self.data[pos..pos + max_length];
+        let mut best = cmp::max(min_length, MIN_MATCH - 1);
+        let mut found = None;
+
+        let mut candidate = self.head[self.hash(pos)] as usize;
+        let mut chain = self.max_chain;
+        while candidate > 0 && chain > 0 {
+            let start = candidate - 1;
+            if start >= pos || pos - start > self.window_size {
+                break;
+            }
+            let previous = &self.data[start..start + max_length];
+            if previous[best] == current[best] {
+                let length = previous
+                    .iter()
+                    .zip(current)
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if length > best && (length > MIN_MATCH || pos - start <= TOO_FAR) {
+                    best = length;
+                    found = Some(Match {
+                        length,
+                        distance: pos - start,
+                    });
+                    if length == max_length {
+                        break;
+                    }
+                }
+            }
+            let next = self.prev[start & mask] as usize;
+            // Entries older than the chain buffer were overwritten by newer positions
+            if next >= candidate {
+                break;
+            }
+            candidate = next;
+            chain -= 1;
+        }
+        found
+    }
+}
+
+/// Splits data into literals and matches. With `lazy`, a match is only taken if the
+/// next position does not start a longer one.
+pub fn parse(
+    data: &[u8],
+    window_size: usize,
+    max_match: usize,
+    max_chain: usize,
+    lazy: bool,
+) -> Vec<Token> {
+    parse_from(data, 0, window_size, max_match, max_chain, lazy)
+}
+
+/// Like `parse`, but only splits `data[start..]`, matches may refer to the data before it
+pub fn parse_from(
+    data: &[u8],
+    start: usize,
+    window_size: usize,
+    max_match: usize,
+    max_chain: usize,
+    lazy: bool,
+) -> Vec<Token> {
+    let mut finder = MatchFinder::new(data, window_size, max_match, max_chain);
+    for pos in 0..start {
+        finder.insert(pos);
+    }
+    let mut tokens = Vec::with_capacity((data.len() - start) / 4);
+    let mut pos = start;
+    let mut current = finder.find(pos, 0);
+    finder.insert(pos);
+
+    while pos < data.len() {
+        let found = match current {
+            Some(found) => found,
+            None => {
+                tokens.push(Token::Literal(data[pos]));
+                pos += 1;
+                current = finder.find(pos, 0);
+                finder.insert(pos);
+                continue;
+            }
+        };
+
+        // Positions up to `inserted` are already in the chains
+        let mut inserted = pos;
+        if lazy && found.length < LAZY_LIMIT {
+            let next = finder.find(pos + 1, found.length);
+            finder.insert(pos + 1);
+            inserted = pos + 1;
+            if next.is_some() {
+                tokens.push(Token::Literal(data[pos]));
+                pos += 1;
+                current = next;
+                continue;
+            }
+        }
+
+        tokens.push(Token::Match {
+            length: found.length,
+            distance: found.distance,
+        });
+        for p in inserted + 1..pos + found.length {
+            finder.insert(p);
+        }
+        pos += found.length;
+        current = finder.find(pos, 0);
+        finder.insert(pos);
+    }
+    tokens
+}
@@ -0,0 +1,403 @@
+use std::cmp;
+
+use bitbit::{BitReader, BitWriter};
+
+use crate::codec::Codec;
+use crate::error::{ComprsError, Result};
+
+use super::arithmetic_coder::decoder::ArithmeticDecoder;
+use super::arithmetic_coder::encoder::ArithmeticEncoder;
+use super::arithmetic_coder::{EntropyDecoder, EntropyEncoder};
+use super::arithmetic_coder::{
+    FlatFrequencyTable, FrequencyTableMut, SimpleFrequencyTable, Symbol,
+};
+use super::MAX_CAPACITY;
+
+use self::matcher::{Token, MIN_MATCH};
+
+pub mod matcher;
+
+const NUM_BITS: usize = 32;
+/// Longest match of the stage
+pub const MAX_MATCH: usize = 1024;
+/// Default exponent of the window size
+pub const WINDOW_BITS: u8 = 22;
+/// Bounds of the window exponent
+pub const MIN_WINDOW_BITS: u8 = 8;
+pub const MAX_WINDOW_BITS: u8 = 24;
+/// Default number of chain entries compared per position
+pub const MAX_CHAIN: u16 = 64;
+/// Symbol of the token stream which ends the data, literals come before it and length slots after it
+const END: Symbol = 256;
+/// Distances are coded depending on the length of their match, up to this many
+const DISTANCE_CONTEXTS: usize = 4;
+/// Total at which the token frequencies are halved
+const RESCALE_LIMIT: usize = 1 << 16;
+/// Extra bits are coded in chunks of at most this size
+const EXTRA_CHUNK_BITS: u32 = 8;
+
+/// Parameters of the match finder
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Params {
+    /// Matches reach back up to `1 << window_bits` bytes
+    pub window_bits: u8,
+    /// Number of earlier positions compared for each match
+    pub max_chain: u16,
+    /// Emit a literal instead of a match if the next position starts a longer match
+    pub lazy: bool,
+}
+
+impl Params {
+    /// Checks that the parameters can be used for coding
+    pub fn validate(&self) -> Result<()> {
+        if self.window_bits < MIN_WINDOW_BITS || self.window_bits > MAX_WINDOW_BITS {
+            return Err(ComprsError::InvalidTransform(format!(
+                "LZ77 window bits must be between {} and {}",
+                MIN_WINDOW_BITS, MAX_WINDOW_BITS
+            )));
+        }
+        if self.max_chain == 0 {
+            return Err(ComprsError::InvalidTransform(String::from(
+                "LZ77 chain length must be positive",
+            )));
+        }
+        Ok(())
+    }
+
+    /// Returns the largest distance of a match
+    pub fn window_size(&self) -> usize {
+        1 << self.window_bits
+    }
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Params {
+            window_bits: WINDOW_BITS,
+            max_chain: MAX_CHAIN,
+            lazy: true,
+        }
+    }
+}
+
+/// LZ77 with an arithmetic coded token stream
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Lz77 {
+    pub params: Params,
+}
+
+impl Codec for Lz77 {
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        apply(data, &self.params)
+    }
+
+    /// Restores data using the parameters stored in the encoded data
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        reduce(data)
+    }
+}
+
+/// Splits a value into a slot and extra bits. Values below four have their own slot,
+/// above that each power of two is split into two slots.
+fn slot(value: usize) -> (Symbol, u32, usize) {
+    if value < 4 {
+        return (value as Symbol, 0, 0);
+    }
+    let bits = value.ilog2();
+    let slot = 2 * bits as usize + (value >> (bits - 1) & 1);
+    (slot as Symbol, bits - 1, value & ((1 << (bits - 1)) - 1))
+}
+
+/// Returns the smallest value and the number of extra bits of a slot
+fn slot_base(slot: Symbol) -> (usize, u32) {
+    if slot < 4 {
+        return (slot as usize, 0);
+    }
+    let bits = u32::from(slot / 2);
+    ((2 | slot as usize & 1) << (bits - 1), bits - 1)
+}
+
+/// Adaptive frequencies of the tokens
+struct TokenModel {
+    /// Literals, the end symbol and the length slots
+    symbols: SimpleFrequencyTable,
+    distances: Vec<SimpleFrequencyTable>,
+    max_distance: usize,
+}
+
+impl TokenModel {
+    fn new(window_size: usize) -> Self {
+        let length_slots = slot(MAX_MATCH - MIN_MATCH).0 + 1;
+        let distance_slots = slot(window_size - 1).0 + 1;
+        TokenModel {
+            symbols: TokenModel::initial_frequencies(END + 1 + length_slots),
+            distances: (0..DISTANCE_CONTEXTS)
+                .map(|_| TokenModel::initial_frequencies(distance_slots))
+                .collect(),
+            max_distance: window_size,
+        }
+    }
+
+    fn initial_frequencies(num_symbols: Symbol) -> SimpleFrequencyTable {
+        let mut freqs = SimpleFrequencyTable::new(num_symbols);
+        for symbol in 0..num_symbols {
+            freqs.set(symbol, 1);
+        }
+        freqs
+    }
+
+    fn distance_context(length: usize) -> usize {
+        cmp::min(length - MIN_MATCH, DISTANCE_CONTEXTS - 1)
+    }
+
+    fn write_symbol<E: EntropyEncoder>(&mut self, encoder: &mut E, symbol: Symbol) -> Result<()> {
+        encoder.write(&mut self.symbols, symbol)?;
+        self.symbols.increment_limited(symbol, RESCALE_LIMIT);
+        Ok(())
+    }
+
+    fn write_extra<E: EntropyEncoder>(encoder: &mut E, bits: u32, value: usize) -> Result<()> {
+        let mut remaining = bits;
+        while remaining > 0 {
+            let chunk = cmp::min(remaining, EXTRA_CHUNK_BITS);
+            remaining -= chunk;
+            let mut table = FlatFrequencyTable::new(1 << chunk);
+            encoder.write(
+                &mut table,
+                (value >> remaining & ((1 << chunk) - 1)) as Symbol,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn read_extra<D: EntropyDecoder>(decoder: &mut D, bits: u32) -> Result<usize> {
+        let mut value = 0;
+        let mut remaining = bits;
+        while remaining > 0 {
+            let chunk = cmp::min(remaining, EXTRA_CHUNK_BITS);
+            remaining -= chunk;
+            let mut table = FlatFrequencyTable::new(1 << chunk);
+            value = value << chunk | decoder.read(&mut table)? as usize;
+        }
+        Ok(value)
+    }
+
+    fn write<E: EntropyEncoder>(&mut self, encoder: &mut E, token: Token) -> Result<()> {
+        match token {
+            Token::Literal(byte) => self.write_symbol(encoder, Symbol::from(byte)),
+            Token::Match { length, distance } => {
+                let (length_slot, bits, extra) = slot(length - MIN_MATCH);
+                self.write_symbol(encoder, END + 1 + length_slot)?;
+                TokenModel::write_extra(encoder, bits, extra)?;
+
+                let table = &mut self.distances[TokenModel::distance_context(length)];
+                let (distance_slot, bits, extra) = slot(distance - 1);
+                encoder.write(table, distance_slot)?;
+                table.increment_limited(distance_slot, RESCALE_LIMIT);
+                TokenModel::write_extra(encoder, bits, extra)
+            }
+        }
+    }
+
+    /// Returns the next token, or None at the end of the data
+    fn read<D: EntropyDecoder>(&mut self, decoder: &mut D) -> Result<Option<Token>> {
+        let symbol = decoder.read(&mut self.symbols)?;
+        self.symbols.increment_limited(symbol, RESCALE_LIMIT);
+        if symbol < END {
+            return Ok(Some(Token::Literal(symbol as u8)));
+        }
+        if symbol == END {
+            return Ok(None);
+        }
+
+        let (base, bits) = slot_base(symbol - END - 1);
+        let length = MIN_MATCH + base + TokenModel::read_extra(decoder, bits)?;
+        if length > MAX_MATCH {
+            return Err(ComprsError::corrupt("invalid LZ77 match length"));
+        }
+
+        let table = &mut self.distances[TokenModel::distance_context(length)];
+        let distance_slot = decoder.read(table)?;
+        table.increment_limited(distance_slot, RESCALE_LIMIT);
+        let (base, bits) = slot_base(distance_slot);
+        let distance = 1 + base + TokenModel::read_extra(decoder, bits)?;
+        if distance > self.max_distance {
+            return Err(ComprsError::corrupt("invalid LZ77 match distance"));
+        }
+        Ok(Some(Token::Match { length, distance }))
+    }
+}
+
+pub fn apply(data: &[u8], params: &Params) -> Result<Vec<u8>> {
+    params.validate()?;
+    let mut buffer = Vec::with_capacity(data.len() / 2 + 16);
+    bincode::serialize_into(&mut buffer, params)?;
+    bincode::serialize_into(&mut buffer, &(data.len() as u64))?;
+
+    let tokens = matcher::parse(
+        data,
+        params.window_size(),
+        MAX_MATCH,
+        params.max_chain as usize,
+        params.lazy,
+    );
+    let header_len = buffer.len();
+    let mut encoder = ArithmeticEncoder::new(BitWriter::new(buffer), NUM_BITS);
+    let mut model = TokenModel::new(params.window_size());
+    for token in tokens {
+        model.write(&mut encoder, token)?;
+    }
+    model.write_symbol(&mut encoder, END)?;
+    encoder.finish()?;
+
+    // The decoder starts by reading a full state, short outputs are padded with zeros
+    let mut buffer = encoder.inner_ref().clone();
+    buffer.resize(cmp::max(buffer.len(), header_len + NUM_BITS / 8), 0);
+    Ok(buffer)
+}
+
+pub fn reduce(mut data: &[u8]) -> Result<Vec<u8>> {
+    let params: Params = bincode::deserialize_from(&mut data)?;
+    let len: u64 = bincode::deserialize_from(&mut data)?;
+    params
+        .validate()
+        .map_err(|_| ComprsError::corrupt("invalid LZ77 parameters"))?;
+
+    let mut decoder = ArithmeticDecoder::new(BitReader::new(data), NUM_BITS)
+        .map_err(|_| ComprsError::corrupt("truncated LZ77 data"))?;
+    let mut model = TokenModel::new(params.window_size());
+    let mut decoded = Vec::with_capacity(cmp::min(len as usize, MAX_CAPACITY));
+    while let Some(token) = model.read(&mut decoder)? {
+        match token {
+            Token::Literal(byte) => decoded.push(byte),
+            Token::Match { length, distance } => {
+                if distance > decoded.len() {
+                    return Err(ComprsError::corrupt("LZ77 match before start of data"));
+                }
+                let start = decoded.len() - distance;
+                if distance >= length {
+                    decoded.extend_from_within(start..start + length);
+                } else {
+                    // Overlapping copies repeat the bytes written by themselves
+                    for i in start..start + length {
+                        decoded.push(decoded[i]);
+                    }
+                }
+            }
+        }
+        if decoded.len() as u64 > len {
+            return Err(ComprsError::corrupt("LZ77 data exceeds stored length"));
+        }
+    }
+
+    if decoded.len() as u64 != len {
+        return Err(ComprsError::corrupt(
+            "LZ77 data is shorter than stored length",
+        ));
+    }
+    decoder.check_end()?;
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ComprsError;
+    use crate::utils::random_bytes;
+
+    use super::matcher::{parse, Token};
+    use super::{apply, reduce, slot, slot_base, Params};
+
+    #[test]
+    fn test_slots() {
+        for value in (0..5000).chain((1 << 24) - 3..1 << 24) {
+            let (slot, bits, extra) = slot(value);
+            let (base, base_bits) = slot_base(slot);
+            assert_eq!(bits, base_bits);
+            assert!(extra < 1 << bits);
+            assert_eq!(base + extra, value);
+        }
+        assert_eq!(slot((1 << 24) - 1).0, 47);
+    }
+
+    #[test]
+    fn test_parse() {
+        let tokens = parse(b"abcabcabcabcx", 1 << 8, 258, 16, true);
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Literal(b'a'),
+                Token::Literal(b'b'),
+                Token::Literal(b'c'),
+                Token::Match {
+                    length: 9,
+                    distance: 3
+                },
+                Token::Literal(b'x'),
+            ]
+        );
+
+        // Lazy matching prefers the longer match starting one byte later
+        let data = b"abc_bcdefg_abcdefg";
+        let greedy = parse(data, 1 << 8, 258, 16, false);
+        let lazy = parse(data, 1 << 8, 258, 16, true);
+        assert!(greedy.contains(&Token::Match {
+            length: 3,
+            distance: 11
+        }));
+        assert!(lazy.contains(&Token::Match {
+            length: 6,
+            distance: 8
+        }));
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let text = "Lorem ipsum dolor sit amet, consectetur adipiscing elit. ".repeat(40);
+        let mut far = random_bytes(20_000);
+        far.extend_from_within(..5000);
+        let inputs: Vec<Vec<u8>> = vec![
+            Vec::new(),
+            vec![42],
+            b"aaaa".to_vec(),
+            vec![7; 100_000],
+            text.into_bytes(),
+            far,
+        ];
+        let params = [
+            Params::default(),
+            Params {
+                window_bits: 8,
+                max_chain: 4,
+                lazy: false,
+            },
+        ];
+        for params in &params {
+            for data in &inputs {
+                let encoded = apply(data, params).unwrap();
+                assert_eq!(&reduce(&encoded).unwrap(), data);
+            }
+        }
+
+        // The repeat far back is found with the default window only
+        let data = &inputs[5];
+        let wide = apply(data, &params[0]).unwrap().len();
+        let narrow = apply(data, &params[1]).unwrap().len();
+        assert!(wide + 4000 < narrow);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let params = Params {
+            window_bits: 30,
+            ..Params::default()
+        };
+        assert!(apply(b"data", &params).is_err());
+
+        let data = "abracadabra".repeat(10).into_bytes();
+        let encoded = apply(&data, &Params::default()).unwrap();
+        match reduce(&encoded[..encoded.len() / 2]) {
+            Err(ComprsError::Corrupt(_)) => (),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
@@ -1,23 +1,52 @@
 use std::fmt;
-use std::io::{BufReader, Read, Result};
+use std::io::Read;
+use std::str::FromStr;
 
+use crate::error::{ComprsError, Result};
 use crate::utils::calc_entropy;
 
+use self::mtf::variants::Variant;
+
+pub use self::preset::Preset;
+pub use self::stream::{ComprsDecoder, ComprsEncoder};
+
 pub mod arithmetic_coder;
 pub mod bwt;
+pub mod bzip2;
+pub mod deflate;
+pub mod header;
+pub mod huffman;
+pub mod lipt;
+pub mod lz77;
 pub mod mtf;
 pub mod ppm;
+pub mod preset;
+pub mod rans;
 pub mod rle;
 pub mod startransform;
+pub mod stream;
+pub mod text;
+
+/// Largest output buffer a decoder reserves up front, as stored lengths are not trusted
+const MAX_CAPACITY: usize = 1 << 24;
 
 #[repr(u8)]
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Transform {
     BWT,
     MTF,
     RLE,
     ST,
     PPM,
+    RANS,
+    HUFFMAN,
+    LZ77,
+    LIPT,
+    TEXT,
+    MTF1,
+    MTF2,
+    SMTF,
+    WFC,
 }
 
 impl fmt::Display for Transform {
@@ -28,11 +57,52 @@ impl fmt::Display for Transform {
             Transform::RLE => "RLE",
             Transform::ST => "ST",
             Transform::PPM => "PPM",
+            Transform::RANS => "RANS",
+            Transform::HUFFMAN => "HUFFMAN",
+            Transform::LZ77 => "LZ77",
+            Transform::LIPT => "LIPT",
+            Transform::TEXT => "TEXT",
+            Transform::MTF1 => "MTF1",
+            Transform::MTF2 => "MTF2",
+            Transform::SMTF => "SMTF",
+            Transform::WFC => "WFC",
         };
         write!(f, "{}", printable)
     }
 }
 
+impl FromStr for Transform {
+    type Err = ComprsError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "bwt" => Ok(Transform::BWT),
+            "mtf" => Ok(Transform::MTF),
+            "rle" => Ok(Transform::RLE),
+            "st" => Ok(Transform::ST),
+            "ppm" => Ok(Transform::PPM),
+            "rans" => Ok(Transform::RANS),
+            "huffman" => Ok(Transform::HUFFMAN),
+            "lz77" => Ok(Transform::LZ77),
+            "lipt" => Ok(Transform::LIPT),
+            "text" => Ok(Transform::TEXT),
+            "mtf1" => Ok(Transform::MTF1),
+            "mtf2" => Ok(Transform::MTF2),
+            "smtf" => Ok(Transform::SMTF),
+            "wfc" => Ok(Transform::WFC),
+            _ => Err(ComprsError::InvalidTransform(format!(
+                "unknown transform '{}'",
+                s
+            ))),
+        }
+    }
+}
+
+/// Parses a comma separated list of transforms like "bwt,mtf,ppm"
+pub fn parse_pipeline(s: &str) -> Result<Vec<Transform>> {
+    s.split(',').map(Transform::from_str).collect()
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct TData {
     pub transforms: Vec<Transform>,
@@ -40,11 +110,9 @@ pub struct TData {
 }
 
 impl TData {
-    /// Applies given transform methods to reader input
-    pub fn encode<R: Read>(reader: R) -> Result<TData> {
-        let mut buffer = Vec::with_capacity(1024 * 1024);
-
-        BufReader::new(reader).read_to_end(&mut buffer)?;
+    /// Applies the transforms of the given preset to a single block of input
+    pub fn encode(data: &[u8], preset: &Preset) -> Result<TData> {
+        let mut buffer = data.to_vec();
 
         debug!("DEBUG: Size before preprocessing: {}", &buffer.len());
         debug!(
@@ -52,21 +120,34 @@ impl TData {
             calc_entropy(&buffer)
         );
 
-        let transforms = vec![
-            //Transform::ST, FIXME: currently broken
-            Transform::BWT,
-            Transform::MTF,
-            Transform::PPM,
-        ];
-
+        let transforms = preset.transforms.clone();
         for transform in &transforms {
-            println!("  -> {} ", transform);
+            debug!("DEBUG: -> {}", transform);
             buffer = match transform {
-                Transform::ST => startransform::apply(&buffer),
-                Transform::BWT => bwt::apply(&buffer),
+                Transform::ST => startransform::apply(&buffer)?,
+                Transform::BWT => bwt::apply(&buffer, preset.bwt_chunk_size)?,
                 Transform::MTF => mtf::apply(&buffer),
                 Transform::RLE => rle::apply(&buffer)?,
-                Transform::PPM => ppm::apply(&buffer)?,
+                Transform::PPM => ppm::apply(&buffer, &preset.ppm)?,
+                Transform::RANS => rans::apply(&buffer, preset.rans)?,
+                Transform::HUFFMAN => huffman::apply(&buffer)?,
+                Transform::LZ77 => lz77::apply(&buffer, &preset.lz77)?,
+                Transform::LIPT => lipt::apply(&buffer, preset.lipt)?,
+                Transform::TEXT => text::apply(&buffer)?,
+                Transform::MTF1 => mtf::variants::apply(&buffer, Variant::Mtf1)?,
+                Transform::MTF2 => mtf::variants::apply(&buffer, Variant::Mtf2)?,
+                Transform::SMTF => mtf::variants::apply(
+                    &buffer,
+                    Variant::Sticky {
+                        threshold: preset.mtf.threshold,
+                    },
+                )?,
+                Transform::WFC => mtf::variants::apply(
+                    &buffer,
+                    Variant::Wfc {
+                        window: preset.mtf.window,
+                    },
+                )?,
             };
         }
 
@@ -83,24 +164,149 @@ impl TData {
         let mut buffer = self.buffer;
 
         for transform in self.transforms.iter().rev() {
-            println!("  -> {} ", transform);
+            debug!("DEBUG: -> {}", transform);
             buffer = match transform {
-                Transform::BWT => bwt::reduce(&buffer),
+                Transform::BWT => bwt::reduce(&buffer)?,
                 Transform::MTF => mtf::reduce(&buffer),
-                Transform::RLE => rle::reduce(&buffer),
+                Transform::RLE => rle::reduce(&buffer)?,
                 Transform::PPM => ppm::reduce(&buffer)?,
-                _ => unimplemented!("not implemented"),
+                Transform::RANS => rans::reduce(&buffer)?,
+                Transform::HUFFMAN => huffman::reduce(&buffer)?,
+                Transform::LZ77 => lz77::reduce(&buffer)?,
+                Transform::ST => startransform::reduce(&buffer)?,
+                Transform::LIPT => lipt::reduce(&buffer)?,
+                Transform::TEXT => text::reduce(&buffer)?,
+                Transform::MTF1 | Transform::MTF2 | Transform::SMTF | Transform::WFC => {
+                    mtf::variants::reduce(&buffer)?
+                }
             };
         }
         Ok(buffer)
     }
 }
 
-pub fn encode_pipeline<R: Read>(reader: R) -> Result<Vec<u8>> {
-    Ok(bincode::serialize(&TData::encode(reader)?).expect("unable to serialize data"))
+/// Compresses the whole reader input into an in-memory buffer
+pub fn encode_pipeline<R: Read>(mut reader: R, preset: Preset) -> Result<Vec<u8>> {
+    let mut encoder = ComprsEncoder::with_preset(Vec::new(), preset);
+    std::io::copy(&mut reader, &mut encoder)?;
+    encoder.finish()
 }
 
+/// Decompresses the whole reader input into an in-memory buffer.
+/// Fails if the container header, length or checksum do not match.
 pub fn decode_pipeline<R: Read>(reader: R) -> Result<Vec<u8>> {
-    let data = bincode::deserialize_from::<R, TData>(reader).expect("unable to deserialize data");
-    data.decode()
+    let mut buffer = Vec::new();
+    ComprsDecoder::new(reader).read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use crate::error::{ComprsError, Result};
+    use crate::utils::xorshift;
+
+    use super::preset::MAX_LEVEL;
+    use super::Transform;
+    use super::{decode_pipeline, encode_pipeline, parse_pipeline, ComprsEncoder, Preset};
+
+    fn pipeline_error(s: &str) -> String {
+        match parse_pipeline(s) {
+            Err(ComprsError::InvalidTransform(message)) => message,
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_parse_pipeline() {
+        assert_eq!(
+            parse_pipeline("bwt, MTF,rle,ppm").unwrap(),
+            vec![
+                Transform::BWT,
+                Transform::MTF,
+                Transform::RLE,
+                Transform::PPM
+            ]
+        );
+        assert_eq!(pipeline_error("bwt,lzw"), "unknown transform 'lzw'");
+    }
+
+    #[test]
+    fn test_custom_pipelines() -> Result<()> {
+        let data = String::from(
+            "It is a long established fact that a reader will be distracted \
+             by the readable content of a page when looking at its layout.",
+        )
+        .into_bytes();
+
+        for pipeline in &[
+            "ppm",
+            "rle,ppm",
+            "bwt,mtf,rle",
+            "mtf,bwt,ppm,rle",
+            "bwt,bwt",
+            "bwt,mtf,rans",
+            "bwt,mtf,huffman",
+            "lz77",
+            "st,bwt,mtf,ppm",
+            "lipt,bwt,mtf,ppm",
+            "text,ppm",
+            "text,lipt,bwt,mtf,huffman",
+            "bwt,mtf1,ppm",
+            "bwt,mtf2,rle,ppm",
+            "bwt,smtf,rans",
+            "bwt,wfc,huffman",
+        ] {
+            let preset = Preset::default().with_transforms(parse_pipeline(pipeline)?);
+            let compressed = encode_pipeline(data.as_slice(), preset)?;
+            assert_eq!(decode_pipeline(compressed.as_slice())?, data);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_levels() -> Result<()> {
+        let text = [
+            include_str!("../../README.md"),
+            include_str!("../../LICENSE.md"),
+        ]
+        .concat();
+        // Words in random order, enough text for the higher orders of the stronger levels to pay off
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let data = xorshift(1)
+            .take(5_000)
+            .map(|state| words[state as usize % words.len()])
+            .collect::<Vec<_>>()
+            .join(" ")
+            .into_bytes();
+        let mut previous = usize::MAX;
+        for level in 0..=MAX_LEVEL {
+            let compressed = encode_pipeline(data.as_slice(), Preset::from_level(level))?;
+            assert_eq!(decode_pipeline(compressed.as_slice())?, data);
+            // A higher level must not compress text worse
+            assert!(compressed.len() <= previous, "level {}", level);
+            previous = compressed.len();
+        }
+        assert_eq!(
+            Preset::from_level(MAX_LEVEL + 1),
+            Preset::from_level(MAX_LEVEL)
+        );
+
+        // Payloads shorter than a coder state, and a last block of a single byte
+        let mut blocks = data[..2000].to_vec();
+        blocks.push(b'x');
+        for level in 0..=MAX_LEVEL {
+            for small in &[&b""[..], b"x", b"aaaa", &[0; 10]] {
+                let compressed = encode_pipeline(*small, Preset::from_level(level))?;
+                assert_eq!(decode_pipeline(compressed.as_slice())?, *small);
+            }
+            let mut encoder =
+                ComprsEncoder::with_block_size(Vec::new(), Preset::from_level(level), 1000);
+            encoder.write_all(&blocks)?;
+            let compressed = encoder.finish()?;
+            assert_eq!(decode_pipeline(compressed.as_slice())?, blocks);
+        }
+        Ok(())
+    }
 }
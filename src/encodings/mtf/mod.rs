@@ -0,0 +1,214 @@
+use std::io::{ErrorKind, Read, Write};
+
+use crate::codec::Codec;
+use crate::error::Result;
+
+pub mod variants;
+
+/// Number of bytes the streaming variants transform at once
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Move-to-front transform
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Mtf;
+
+impl Codec for Mtf {
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(apply(data))
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(reduce(data))
+    }
+
+    fn encode_stream(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<u64> {
+        transform_stream(reader, writer, encode_byte)
+    }
+
+    fn decode_stream(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<u64> {
+        transform_stream(reader, writer, decode_byte)
+    }
+}
+
+const LOW_BITS: u64 = 0x0101_0101_0101_0101;
+const HIGH_BITS: u64 = 0x8080_8080_8080_8080;
+
+/// Bytes ordered by how recently they were used, the most recent first
+pub(super) struct Alphabet {
+    symbols: [u8; 256],
+}
+
+impl Default for Alphabet {
+    fn default() -> Self {
+        let mut symbols = [0; 256];
+        for (i, symbol) in symbols.iter_mut().enumerate() {
+            *symbol = i as u8;
+        }
+        Alphabet { symbols }
+    }
+}
+
+impl Alphabet {
+    /// Returns the index of a byte, comparing eight bytes at once
+    #[inline]
+    pub(super) fn position(&self, byte: u8) -> usize {
+        if self.symbols[0] == byte {
+            return 0;
+        }
+        let pattern = u64::from(byte) * LOW_BITS;
+        for (i, chunk) in self.symbols.chunks_exact(8).enumerate() {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(chunk);
+            // Bytes equal to the pattern become zero, the lowest zero byte sets its high bit
+            let word = u64::from_le_bytes(bytes) ^ pattern;
+            let zeros = word.wrapping_sub(LOW_BITS) & !word & HIGH_BITS;
+            if zeros != 0 {
+                return i * 8 + (zeros.trailing_zeros() / 8) as usize;
+            }
+        }
+        unreachable!("every byte is in the alphabet")
+    }
+
+    /// Returns the byte at an index
+    #[inline]
+    pub(super) fn get(&self, index: usize) -> u8 {
+        self.symbols[index]
+    }
+
+    /// Moves the byte at `index` to position `target`, shifting the bytes in between
+    #[inline]
+    pub(super) fn move_to(&mut self, index: usize, target: usize) {
+        let byte = self.symbols[index];
+        if target < index {
+            self.symbols.copy_within(target..index, target + 1);
+        } else {
+            self.symbols.copy_within(index + 1..=target, index);
+        }
+        self.symbols[target] = byte;
+    }
+}
+
+#[inline]
+fn encode_byte(alphabet: &mut Alphabet, byte: u8) -> u8 {
+    let index = alphabet.position(byte);
+    alphabet.move_to(index, 0);
+    index as u8
+}
+
+#[inline]
+fn decode_byte(alphabet: &mut Alphabet, index: u8) -> u8 {
+    let byte = alphabet.get(usize::from(index));
+    alphabet.move_to(usize::from(index), 0);
+    byte
+}
+
+pub fn apply(data: &[u8]) -> Vec<u8> {
+    let mut alphabet = Alphabet::default();
+    data.iter()
+        .map(|&byte| encode_byte(&mut alphabet, byte))
+        .collect()
+}
+
+pub fn reduce(data: &[u8]) -> Vec<u8> {
+    let mut alphabet = Alphabet::default();
+    data.iter()
+        .map(|&index| decode_byte(&mut alphabet, index))
+        .collect()
+}
+
+/// Maps each byte from reader to writer in place, keeping the alphabet between reads
+fn transform_stream(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    map: fn(&mut Alphabet, u8) -> u8,
+) -> Result<u64> {
+    let mut alphabet = Alphabet::default();
+    let mut buffer = vec![0; BUFFER_SIZE];
+    let mut written = 0;
+    loop {
+        let len = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        for byte in &mut buffer[..len] {
+            *byte = map(&mut alphabet, *byte);
+        }
+        writer.write_all(&buffer[..len])?;
+        written += len as u64;
+    }
+    writer.flush()?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codec::Codec;
+    use crate::utils::xorshift;
+
+    use super::{apply, reduce, Alphabet, Mtf};
+
+    /// Reference implementation which searches and shifts a vector
+    fn naive_apply(data: &[u8]) -> Vec<u8> {
+        let mut alphabet: Vec<u8> = (0..=255).collect();
+        data.iter()
+            .map(|byte| {
+                let index = alphabet.iter().position(|b| b == byte).unwrap();
+                let byte = alphabet.remove(index);
+                alphabet.insert(0, byte);
+                index as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_apply() {
+        let data = String::from("bananaaa").into_bytes();
+        assert_eq!(apply(&data), vec![98, 98, 110, 1, 1, 1, 0, 0]);
+    }
+
+    #[test]
+    fn test_reduce() {
+        let data = vec![98, 98, 110, 1, 1, 1, 0, 0];
+        assert_eq!(reduce(&data), String::from("bananaaa").into_bytes());
+    }
+
+    #[test]
+    fn test_position() {
+        let mut alphabet = Alphabet::default();
+        for byte in 0..=255 {
+            assert_eq!(alphabet.position(byte), usize::from(byte));
+        }
+        alphabet.move_to(200, 0);
+        assert_eq!(alphabet.position(200), 0);
+        assert_eq!(alphabet.position(0), 1);
+        assert_eq!(alphabet.position(199), 200);
+        assert_eq!(alphabet.position(201), 201);
+        alphabet.move_to(0, 3);
+        assert_eq!(alphabet.position(200), 3);
+        assert_eq!(alphabet.position(2), 2);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        // Skewed towards few symbols, as after a BWT
+        let data: Vec<u8> = xorshift(0x2545_f491)
+            .take(100_000)
+            .map(|state| (state % 7) as u8 * (state >> 29) as u8)
+            .collect();
+        let encoded = apply(&data);
+        assert_eq!(encoded, naive_apply(&data));
+        assert_eq!(reduce(&encoded), data);
+
+        // The streaming variants keep the alphabet across several reads
+        let mut streamed = Vec::new();
+        Mtf.encode_stream(&mut data.as_slice(), &mut streamed)
+            .unwrap();
+        assert_eq!(streamed, encoded);
+        let mut restored = Vec::new();
+        Mtf.decode_stream(&mut encoded.as_slice(), &mut restored)
+            .unwrap();
+        assert_eq!(restored, data);
+    }
+}
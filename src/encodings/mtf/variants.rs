@@ -0,0 +1,292 @@
+use crate::codec::Codec;
+use crate::error::{ComprsError, Result};
+
+use super::Alphabet;
+
+/// Default position which new bytes of the sticky MTF move to
+pub const THRESHOLD: u8 = 2;
+/// Default number of recent bytes counted by WFC
+pub const WINDOW: u16 = 256;
+/// Largest number of recent bytes counted by WFC, the weights of `WFC_LEVELS` end there
+pub const MAX_WINDOW: u16 = 256;
+/// Weights of an occurrence in WFC by its distance, up to the given distance each.
+/// They fall faster than the ones proposed by Deorowicz, which did worse than MTF
+/// followed by PPM.
+const WFC_LEVELS: [(usize, u32); 5] = [(1, 32768), (4, 4096), (16, 512), (64, 64), (256, 8)];
+
+/// List update rule used instead of moving each byte to the front
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Variant {
+    /// Moves a byte at position 1 to the front and all others to position 1
+    #[default]
+    Mtf1,
+    /// Like MTF-1, but a byte at position 1 only moves to the front if the previous
+    /// index was not zero
+    Mtf2,
+    /// Moves a byte to the front from positions up to `threshold`, otherwise to `threshold`
+    Sticky { threshold: u8 },
+    /// Weighted frequency count, orders bytes by their weighted occurrences within the
+    /// last `window` bytes, recent ones weighing more
+    Wfc { window: u16 },
+}
+
+impl Variant {
+    /// Checks that the parameters can be used for coding
+    pub fn validate(&self) -> Result<()> {
+        match *self {
+            Variant::Wfc { window } if window == 0 || window > MAX_WINDOW => {
+                Err(ComprsError::InvalidTransform(format!(
+                    "WFC window must be between 1 and {}",
+                    MAX_WINDOW
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Parameters of the MTF variants which have any
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Params {
+    /// Position of the sticky MTF
+    pub threshold: u8,
+    /// Window of WFC
+    pub window: u16,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Params {
+            threshold: THRESHOLD,
+            window: WINDOW,
+        }
+    }
+}
+
+/// Move-to-front variant, the rule and its parameters are stored in the encoded data
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MtfVariant {
+    pub variant: Variant,
+}
+
+impl Codec for MtfVariant {
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        apply(data, self.variant)
+    }
+
+    /// Restores data using the variant stored in the encoded data
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        reduce(data)
+    }
+}
+
+/// Weights of WFC which change as occurrences move out of the levels of `WFC_LEVELS`.
+/// A byte whose weight grows moves ahead of bytes with the same weight.
+struct Weights {
+    weights: [u32; 256],
+    /// Distance bounds and weights, cut off at the window
+    levels: Vec<(usize, u32)>,
+    /// Recent bytes, the byte `count - 1 - d` back is at distance `d + 1`
+    history: Vec<u8>,
+    count: usize,
+}
+
+impl Weights {
+    fn new(window: u16) -> Self {
+        let window = usize::from(window);
+        let mut levels = Vec::new();
+        for &(bound, weight) in &WFC_LEVELS {
+            levels.push((bound.min(window), weight));
+            if bound >= window {
+                break;
+            }
+        }
+        Weights {
+            weights: [0; 256],
+            levels,
+            history: vec![0; window + 1],
+            count: 0,
+        }
+    }
+
+    /// Changes the weight of a byte and moves it to keep the weights in descending order
+    fn adjust(&mut self, alphabet: &mut Alphabet, byte: u8, weight: u32) {
+        let weight_at = |index: usize| self.weights[usize::from(alphabet.get(index))];
+        let index = alphabet.position(byte);
+        let mut target = index;
+        if weight > self.weights[usize::from(byte)] {
+            while target > 0 && weight_at(target - 1) <= weight {
+                target -= 1;
+            }
+        } else {
+            while target < 255 && weight_at(target + 1) > weight {
+                target += 1;
+            }
+        }
+        self.weights[usize::from(byte)] = weight;
+        alphabet.move_to(index, target);
+    }
+
+    /// Adds an occurrence of a byte and lowers the weights of older ones crossing a level
+    fn update(&mut self, alphabet: &mut Alphabet, byte: u8) {
+        let size = self.history.len();
+        self.history[self.count % size] = byte;
+        self.count += 1;
+        let weight = self.weights[usize::from(byte)] + self.levels[0].1;
+        self.adjust(alphabet, byte, weight);
+
+        for level in 0..self.levels.len() {
+            let (bound, weight) = self.levels[level];
+            if self.count <= bound {
+                break;
+            }
+            let next = self.levels.get(level + 1).map_or(0, |&(_, next)| next);
+            let old = self.history[(self.count - 1 - bound) % size];
+            let weight = self.weights[usize::from(old)] + next - weight;
+            self.adjust(alphabet, old, weight);
+        }
+    }
+}
+
+/// State of a list update rule, shared by encoding and decoding
+struct List {
+    alphabet: Alphabet,
+    variant: Variant,
+    /// Index of the previous byte, used by MTF-2
+    previous: usize,
+    /// Only used by WFC
+    weights: Option<Box<Weights>>,
+}
+
+impl List {
+    fn new(variant: Variant) -> Self {
+        let weights = match variant {
+            Variant::Wfc { window } => Some(Box::new(Weights::new(window))),
+            _ => None,
+        };
+        List {
+            alphabet: Alphabet::default(),
+            variant,
+            previous: 0,
+            weights,
+        }
+    }
+
+    /// Updates the list after the byte at `index` was coded
+    #[inline]
+    fn update(&mut self, index: usize) {
+        let target = match self.variant {
+            Variant::Mtf1 if index <= 1 => 0,
+            Variant::Mtf2 if index == 0 || (index == 1 && self.previous != 0) => 0,
+            Variant::Mtf1 | Variant::Mtf2 => 1,
+            Variant::Sticky { threshold } => {
+                let threshold = usize::from(threshold);
+                if index <= threshold {
+                    0
+                } else {
+                    threshold
+                }
+            }
+            Variant::Wfc { .. } => {
+                let byte = self.alphabet.get(index);
+                if let Some(weights) = &mut self.weights {
+                    weights.update(&mut self.alphabet, byte);
+                }
+                return;
+            }
+        };
+        self.alphabet.move_to(index, target);
+        self.previous = index;
+    }
+}
+
+pub fn apply(data: &[u8], variant: Variant) -> Result<Vec<u8>> {
+    variant.validate()?;
+    let mut buffer = Vec::with_capacity(data.len() + 8);
+    bincode::serialize_into(&mut buffer, &variant)?;
+
+    let mut list = List::new(variant);
+    for &byte in data {
+        let index = list.alphabet.position(byte);
+        buffer.push(index as u8);
+        list.update(index);
+    }
+    Ok(buffer)
+}
+
+pub fn reduce(mut data: &[u8]) -> Result<Vec<u8>> {
+    let variant: Variant = bincode::deserialize_from(&mut data)?;
+    variant
+        .validate()
+        .map_err(|_| ComprsError::corrupt("invalid MTF variant parameters"))?;
+
+    let mut list = List::new(variant);
+    Ok(data
+        .iter()
+        .map(|&index| {
+            let byte = list.alphabet.get(usize::from(index));
+            list.update(usize::from(index));
+            byte
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::xorshift;
+
+    use super::{apply, reduce, Variant, WINDOW};
+
+    fn indices(data: &[u8], variant: Variant) -> Vec<u8> {
+        let encoded = apply(data, variant).unwrap();
+        let header = bincode::serialize(&variant).unwrap().len();
+        encoded[header..].to_vec()
+    }
+
+    #[test]
+    fn test_apply() {
+        // A single byte within a run only leaves the front with MTF-1
+        let data = b"\x00\x00\x00\x01\x00\x00\x00\x01";
+        assert_eq!(indices(data, Variant::Mtf1), [0, 0, 0, 1, 1, 0, 0, 1]);
+        assert_eq!(indices(data, Variant::Mtf2), [0, 0, 0, 1, 0, 0, 0, 1]);
+        assert_eq!(
+            indices(b"\x05\x05\x03\x05", Variant::Sticky { threshold: 2 }),
+            [5, 2, 4, 0]
+        );
+        assert_eq!(
+            indices(b"\x00\x02\x02\x01\x00", Variant::Wfc { window: 4 }),
+            [0, 2, 0, 2, 2]
+        );
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let data: Vec<u8> = xorshift(0x2545_f491)
+            .take(20_000)
+            .map(|state| (state % 7) as u8 * (state >> 29) as u8)
+            .collect();
+        for &variant in &[
+            Variant::Mtf1,
+            Variant::Mtf2,
+            Variant::Sticky { threshold: 0 },
+            Variant::Sticky { threshold: 3 },
+            Variant::Sticky { threshold: 255 },
+            Variant::Wfc { window: 1 },
+            Variant::Wfc { window: 100 },
+            Variant::Wfc { window: WINDOW },
+        ] {
+            let encoded = apply(&data, variant).unwrap();
+            assert_eq!(reduce(&encoded).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_invalid_window() {
+        assert!(apply(b"data", Variant::Wfc { window: 0 }).is_err());
+        let mut encoded = apply(b"data", Variant::Wfc { window: 16 }).unwrap();
+        // Variant index followed by the little endian window
+        encoded[4] = 0xff;
+        encoded[5] = 0xff;
+        assert!(reduce(&encoded).is_err());
+    }
+}
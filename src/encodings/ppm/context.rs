@@ -1,18 +1,428 @@
-use std::collections::BTreeMap;
+use std::cmp;
+use std::mem;
 
-use super::super::arithmetic_coder::{FrequencyTable, SimpleFrequencyTable, Symbol};
+use super::super::arithmetic_coder::{FrequencyTable, Symbol, SymbolSet};
 
+/// Marks a missing context
+pub const NONE: u32 = u32::MAX;
+/// Marks an entry child that points into the history instead of a context. Used by
+/// unbounded models for contexts which occurred only once.
+pub const LEAF: u32 = 1 << 31;
+
+/// Node of the context tree. The symbols seen in the context are stored in a block of
+/// entries with a power of two size, ordered by descending count.
+#[derive(Clone, Copy, Debug)]
 pub struct Context {
-    pub frequencies: SimpleFrequencyTable,
-    pub sub_ctxs: BTreeMap<Symbol, Context>,
+    /// First entry of the block, unused while the context is empty
+    pub block: u32,
+    /// Number of entries in use
+    pub len: u32,
+    pub escape: u32,
+    /// Sum of the symbol counts and the escape count
+    pub total: u32,
+    /// Context without its oldest symbol, only maintained by unbounded models
+    pub suffix: u32,
+}
+
+/// Symbol seen in a context
+#[derive(Clone, Copy, Debug)]
+pub struct Entry {
+    pub symbol: Symbol,
+    pub count: u32,
+    /// Context extended by this symbol
+    pub child: u32,
+}
+
+const EMPTY_ENTRY: Entry = Entry {
+    symbol: 0,
+    count: 0,
+    child: NONE,
+};
+
+/// Arena of contexts and entry blocks, addressed by u32 indices. Blocks are moved to a
+/// larger block when full and released blocks are reused for blocks of the same size.
+/// Memory usage is computed from the arena sizes, so that encoder and decoder reach
+/// the memory limit at the same position.
+pub struct ContextTree {
+    contexts: Vec<Context>,
+    entries: Vec<Entry>,
+    // Released blocks, indexed by the base-2 log of their size
+    free: Vec<Vec<u32>>,
+    memory_limit: usize,
+}
+
+impl ContextTree {
+    /// Index of the order-0 context
+    pub const ROOT: u32 = 0;
+
+    /// Creates a tree with an empty root context, reserving memory for at most `capacity` nodes.
+    /// Each node is an entry plus half a context, and the reservation stays within the memory limit.
+    pub fn new(memory_limit: usize, capacity: usize) -> Self {
+        let node_size = mem::size_of::<Entry>() + mem::size_of::<Context>() / 2;
+        let budget = cmp::min(capacity.saturating_mul(node_size), memory_limit);
+        let capacity = budget / node_size;
+        let mut tree = ContextTree {
+            contexts: Vec::with_capacity(capacity / 2 + 1),
+            entries: Vec::with_capacity(capacity),
+            free: Vec::new(),
+            memory_limit,
+        };
+        tree.reset();
+        tree
+    }
+
+    /// Drops all contexts except an empty root context
+    pub fn reset(&mut self) {
+        self.contexts.clear();
+        self.entries.clear();
+        for blocks in self.free.iter_mut() {
+            blocks.clear();
+        }
+        self.add_context();
+    }
+
+    /// Returns the number of bytes used by contexts and entries
+    pub fn memory_usage(&self) -> usize {
+        self.contexts.len() * mem::size_of::<Context>()
+            + self.entries.len() * mem::size_of::<Entry>()
+    }
+
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.memory_usage() >= self.memory_limit
+    }
+
+    #[inline]
+    pub fn context(&self, ctx: u32) -> &Context {
+        &self.contexts[ctx as usize]
+    }
+
+    #[inline]
+    pub fn entry(&self, entry: u32) -> &Entry {
+        &self.entries[entry as usize]
+    }
+
+    /// Returns the entries of a context, ordered by descending count
+    #[inline]
+    pub fn entries(&self, ctx: u32) -> &[Entry] {
+        let context = self.context(ctx);
+        let block = context.block as usize;
+        &self.entries[block..block + context.len as usize]
+    }
+
+    /// Returns the index of the entry of `symbol` in the given context
+    pub fn find(&self, ctx: u32, symbol: Symbol) -> Option<u32> {
+        self.entries(ctx)
+            .iter()
+            .position(|entry| entry.symbol == symbol)
+            .map(|i| self.context(ctx).block + i as u32)
+    }
+
+    /// Adds an empty context with an escape count of one
+    pub fn add_context(&mut self) -> u32 {
+        self.contexts.push(Context {
+            block: 0,
+            len: 0,
+            escape: 1,
+            total: 1,
+            suffix: NONE,
+        });
+        (self.contexts.len() - 1) as u32
+    }
+
+    #[inline]
+    pub fn set_suffix(&mut self, ctx: u32, suffix: u32) {
+        self.contexts[ctx as usize].suffix = suffix;
+    }
+
+    #[inline]
+    pub fn set_child(&mut self, entry: u32, child: u32) {
+        self.entries[entry as usize].child = child;
+    }
+
+    /// Appends an entry with a count of zero to a context, moving its entries to a larger block if needed
+    pub fn add_entry(&mut self, ctx: u32, symbol: Symbol) -> u32 {
+        let Context { block, len, .. } = *self.context(ctx);
+        let block = if len == 0 {
+            self.allocate(0)
+        } else if len.is_power_of_two() {
+            let class = len.trailing_zeros() as usize;
+            let larger = self.allocate(class + 1);
+            let (from, to) = (block as usize, larger as usize);
+            self.entries.copy_within(from..from + len as usize, to);
+            self.free[class].push(block);
+            larger
+        } else {
+            block
+        };
+
+        let index = block + len;
+        self.entries[index as usize] = Entry {
+            symbol,
+            ..EMPTY_ENTRY
+        };
+        let context = &mut self.contexts[ctx as usize];
+        context.block = block;
+        context.len += 1;
+        index
+    }
+
+    /// Returns a block of `1 << class` entries
+    fn allocate(&mut self, class: usize) -> u32 {
+        if self.free.len() <= class {
+            self.free.resize(class + 1, Vec::new());
+        }
+        match self.free[class].pop() {
+            Some(block) => block,
+            None => {
+                let block = self.entries.len();
+                self.entries.resize(block + (1 << class), EMPTY_ENTRY);
+                block as u32
+            }
+        }
+    }
+
+    /// Returns the child context of an entry, creating it if `create` is set
+    pub fn child(&mut self, entry: u32, create: bool) -> u32 {
+        let child = self.entry(entry).child;
+        if child != NONE || !create {
+            return child;
+        }
+        let child = self.add_context();
+        self.set_child(entry, child);
+        child
+    }
+
+    /// Adds to the count of an entry and moves it in front of entries with lower counts.
+    /// Returns the new index of the entry.
+    pub fn add_count(&mut self, ctx: u32, entry: u32, count: u32) -> u32 {
+        let block = self.context(ctx).block;
+        self.contexts[ctx as usize].total += count;
+        self.entries[entry as usize].count += count;
+
+        let mut index = entry as usize;
+        while index > block as usize && self.entries[index - 1].count < self.entries[index].count {
+            self.entries.swap(index - 1, index);
+            index -= 1;
+        }
+        index as u32
+    }
+
+    #[inline]
+    pub fn add_escape(&mut self, ctx: u32, count: u32) {
+        let context = &mut self.contexts[ctx as usize];
+        context.escape += count;
+        context.total += count;
+    }
+
+    /// Divides all counts of a context by `1 << shift`, rounding up so that symbols which
+    /// occurred remain codable
+    pub fn rescale(&mut self, ctx: u32, shift: u32) {
+        let Context { block, len, .. } = *self.context(ctx);
+        let divisor = 1 << shift;
+        let mut total = self.context(ctx).escape.div_ceil(divisor);
+        for entry in &mut self.entries[block as usize..(block + len) as usize] {
+            entry.count = entry.count.div_ceil(divisor);
+            total += entry.count;
+        }
+        let context = &mut self.contexts[ctx as usize];
+        context.escape = context.escape.div_ceil(divisor);
+        context.total = total;
+    }
+}
+
+/// Frequencies of the entries of a context with the excluded symbols masked out, followed
+/// by the escape. The arithmetic coder codes entry positions instead of symbols. As entries
+/// are ordered by descending count, frequent symbols are found after a few steps, which
+/// is faster than keeping cumulative counts that change with every exclusion and reordering.
+pub struct ContextTable<'a> {
+    entries: &'a [Entry],
+    excluded: &'a SymbolSet,
+    escape: usize,
+    total: usize,
+}
+
+impl<'a> ContextTable<'a> {
+    pub fn new(tree: &'a ContextTree, ctx: u32, excluded: &'a SymbolSet) -> Self {
+        let entries = tree.entries(ctx);
+        let context = tree.context(ctx);
+        let mut total = context.total as usize;
+        if !excluded.is_empty() {
+            total -= entries
+                .iter()
+                .filter(|entry| excluded.contains(entry.symbol))
+                .map(|entry| entry.count as usize)
+                .sum::<usize>();
+        }
+        ContextTable {
+            entries,
+            excluded,
+            escape: context.escape as usize,
+            total,
+        }
+    }
+
+    /// Returns the position of a symbol, if it can be coded in this context
+    pub fn position(&self, symbol: Symbol) -> Option<Symbol> {
+        self.entries
+            .iter()
+            .position(|entry| entry.symbol == symbol)
+            .filter(|position| self.get(*position as Symbol) > 0)
+            .map(|position| position as Symbol)
+    }
+
+    /// Returns the symbol at a position, or None for the escape
+    pub fn symbol(&self, position: Symbol) -> Option<Symbol> {
+        self.entries
+            .get(position as usize)
+            .map(|entry| entry.symbol)
+    }
+
+    /// Returns the position of the escape
+    #[inline]
+    pub fn escape(&self) -> Symbol {
+        self.entries.len() as Symbol
+    }
+
+    /// Returns the total without the escape
+    #[inline]
+    pub fn symbol_total(&self) -> usize {
+        self.total - self.escape
+    }
+
+    /// Replaces the escape frequency
+    pub fn set_escape(&mut self, escape: usize) {
+        self.total = self.total - self.escape + escape;
+        self.escape = escape;
+    }
 }
 
-impl Context {
+impl<'a> FrequencyTable for ContextTable<'a> {
     #[inline]
-    pub fn new(num_symbols: Symbol) -> Self {
-        Context {
-            frequencies: SimpleFrequencyTable::new(num_symbols),
-            sub_ctxs: BTreeMap::new(),
+    fn get(&self, position: Symbol) -> usize {
+        match self.entries.get(position as usize) {
+            Some(entry) if self.excluded.contains(entry.symbol) => 0,
+            Some(entry) => entry.count as usize,
+            None => self.escape,
         }
     }
+
+    #[inline]
+    fn get_low(&self, position: Symbol) -> usize {
+        self.interval(position).0
+    }
+
+    #[inline]
+    fn get_high(&self, position: Symbol) -> usize {
+        self.interval(position).1
+    }
+
+    #[inline]
+    fn get_symbol_limit(&self) -> Symbol {
+        self.entries.len() as Symbol + 1
+    }
+
+    #[inline]
+    fn total(&self) -> usize {
+        self.total
+    }
+
+    #[inline]
+    fn find(&self, value: usize) -> Symbol {
+        self.find_interval(value).0
+    }
+
+    /// Sums the frequencies up to a position in a single scan
+    fn interval(&self, position: Symbol) -> (usize, usize) {
+        let low = (0..position).map(|p| self.get(p)).sum();
+        (low, low + self.get(position))
+    }
+
+    /// Scans the entries until the cumulative frequency exceeds `value`
+    fn find_interval(&self, value: usize) -> (Symbol, usize, usize) {
+        let mut low = 0;
+        for position in 0..self.escape() {
+            let high = low + self.get(position);
+            if high > value {
+                return (position, low, high);
+            }
+            low = high;
+        }
+        (self.escape(), low, low + self.escape)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+
+    use super::super::super::arithmetic_coder::{FrequencyTable, SymbolSet};
+    use super::{Context, ContextTable, ContextTree, Entry, NONE};
+
+    #[test]
+    fn test_tree() {
+        let mut tree = ContextTree::new(1 << 20, 16);
+        let root = ContextTree::ROOT;
+        for (symbol, count) in &[(97, 1), (98, 3), (99, 1)] {
+            let entry = tree.add_entry(root, *symbol);
+            tree.add_count(root, entry, *count);
+        }
+        tree.add_escape(root, 1);
+        // Entries moved to larger blocks and are ordered by count
+        let symbols: Vec<u16> = tree.entries(root).iter().map(|e| e.symbol).collect();
+        assert_eq!(symbols, vec![98, 97, 99]);
+        let a = tree.find(root, 97).unwrap();
+        let b = tree.find(root, 98).unwrap();
+        assert_eq!(b + 1, a);
+        assert_eq!(tree.find(root, 100), None);
+        assert_eq!(tree.context(root).total, 7);
+
+        assert_eq!(tree.child(a, false), NONE);
+        let child = tree.child(a, true);
+        assert_eq!(tree.child(a, false), child);
+        assert_eq!(tree.context(child).total, 1);
+
+        tree.rescale(root, 1);
+        assert_eq!(tree.context(root).total, 5);
+        assert_eq!(tree.entry(b).count, 2);
+
+        let mut excluded = SymbolSet::new(257);
+        let table = ContextTable::new(&tree, root, &excluded);
+        assert_eq!(table.symbol(0), Some(98));
+        assert_eq!(table.total(), 5);
+        assert_eq!(table.find(0), 0);
+        assert_eq!(table.find(2), 1);
+        assert_eq!(table.find(4), table.escape());
+        assert_eq!(table.symbol(table.escape()), None);
+
+        excluded.insert(98);
+        let table = ContextTable::new(&tree, root, &excluded);
+        assert_eq!(table.position(98), None);
+        assert_eq!(table.position(97), Some(1));
+        assert_eq!(table.get_low(1), 0);
+        assert_eq!(table.find(0), 1);
+        assert_eq!(table.total(), 3);
+
+        // Blocks released by the root context are reused
+        let usage = tree.memory_usage();
+        tree.add_entry(child, 100);
+        tree.add_entry(child, 101);
+        assert_eq!(tree.memory_usage(), usage);
+        let symbols: Vec<u16> = tree.entries(child).iter().map(|e| e.symbol).collect();
+        assert_eq!(symbols, vec![100, 101]);
+
+        tree.reset();
+        assert_eq!(tree.find(root, 97), None);
+        assert_eq!(tree.memory_usage(), 20);
+    }
+
+    #[test]
+    fn test_reservation() {
+        // A corrupt length must not reserve more than the memory limit
+        let memory_limit = 1 << 16;
+        let tree = ContextTree::new(memory_limit, usize::MAX);
+        let reserved = tree.contexts.capacity() * mem::size_of::<Context>()
+            + tree.entries.capacity() * mem::size_of::<Entry>();
+        assert!(reserved <= memory_limit + mem::size_of::<Context>());
+    }
 }
@@ -1,149 +1,365 @@
-use std::io::{Read, Result, Write};
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
 
 use bitbit::{BitReader, BitWriter};
 
+use crate::codec::Codec;
+use crate::error::{ComprsError, Result};
+
 use super::arithmetic_coder::decoder::ArithmeticDecoder;
 use super::arithmetic_coder::encoder::ArithmeticEncoder;
-use super::arithmetic_coder::{FrequencyTable, Symbol};
+use super::arithmetic_coder::range::{RangeDecoder, RangeEncoder};
+use super::arithmetic_coder::{Backend, EntropyDecoder, EntropyEncoder};
+use super::arithmetic_coder::{MaskedFrequencyTable, Symbol, SymbolSet};
 
-use self::context::Context;
+use self::context::{ContextTable, ContextTree};
 use self::model::PPMModel;
 
 pub mod context;
 pub mod model;
+pub mod see;
 
-// TODO: create struct to hold all possible encoding parameters
 const ORDER: u8 = 2;
 const EOF: Symbol = 256;
 const SYMBOL_LIMIT: Symbol = 257;
-const ESCAPE_SYMBOL: Symbol = 256;
 const NUM_BITS: usize = 32;
+/// Default exponent of the total at which context frequencies are rescaled
+pub const RESCALE_BITS: u8 = 14;
+/// Bounds of the rescale exponent, the upper one is the highest total the coder can handle
+pub const MIN_RESCALE_BITS: u8 = 10;
+pub const MAX_RESCALE_BITS: u8 = NUM_BITS as u8 - 2;
+/// Default exponent of the divisor of rescaled frequencies, which halves them
+pub const RESCALE_SHIFT: u8 = 1;
+/// Bounds of the divisor exponent, the upper one brings any count down to one
+pub const MIN_RESCALE_SHIFT: u8 = 1;
+pub const MAX_RESCALE_SHIFT: u8 = MAX_RESCALE_BITS;
+/// Default exponent of the memory limit of a model in bytes
+pub const MEMORY_BITS: u8 = 27;
+/// Bounds of the memory limit exponent, the upper one keeps arena indices within u32
+pub const MIN_MEMORY_BITS: u8 = 16;
+pub const MAX_MEMORY_BITS: u8 = 31;
+
+/// Method used to estimate the probability of the escape symbol
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum EscapeMethod {
+    /// Escape count of one per context
+    A,
+    /// Escape count of the number of distinct symbols, symbols are predicted from their second occurrence
+    B,
+    /// Escape count of the number of distinct symbols
+    C,
+    /// Like C, but each symbol gives half a count to the escape on its first occurrence
+    D,
+}
+
+impl fmt::Display for EscapeMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let printable = match *self {
+            EscapeMethod::A => "A",
+            EscapeMethod::B => "B",
+            EscapeMethod::C => "C",
+            EscapeMethod::D => "D",
+        };
+        write!(f, "{}", printable)
+    }
+}
+
+impl FromStr for EscapeMethod {
+    type Err = ComprsError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "a" => Ok(EscapeMethod::A),
+            "b" => Ok(EscapeMethod::B),
+            "c" => Ok(EscapeMethod::C),
+            "d" => Ok(EscapeMethod::D),
+            _ => Err(ComprsError::InvalidTransform(format!(
+                "unknown escape method '{}'",
+                s
+            ))),
+        }
+    }
+}
+
+/// Behaviour of a model that reached its memory limit
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum MemoryPolicy {
+    /// Drop all contexts and start learning from scratch
+    Restart,
+    /// Keep updating the counts of existing contexts, but stop adding contexts and symbols
+    Freeze,
+}
+
+/// Model parameters, stored in front of the compressed data along with the input length
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Params {
+    /// Highest context order, ignored by unbounded models
+    pub order: u8,
+    /// Use contexts of unbounded order as in PPM*
+    pub star: bool,
+    pub escape_method: EscapeMethod,
+    /// The frequencies of a context are rescaled once their total exceeds `1 << rescale_bits`.
+    /// Lower values let the model forget old statistics faster.
+    pub rescale_bits: u8,
+    /// Rescaling divides the frequencies by `1 << rescale_shift`, rounding up. Higher
+    /// values forget more of the old statistics at once.
+    pub rescale_shift: u8,
+    /// The contexts of a model may use up to `1 << memory_bits` bytes
+    pub memory_bits: u8,
+    pub memory_policy: MemoryPolicy,
+    /// Use secondary escape estimation instead of the raw escape counts
+    pub see: bool,
+    pub coder: Backend,
+}
+
+impl Params {
+    /// Checks that the parameters can be used for coding
+    pub fn validate(&self) -> Result<()> {
+        if self.rescale_bits < MIN_RESCALE_BITS || self.rescale_bits > MAX_RESCALE_BITS {
+            return Err(ComprsError::InvalidTransform(format!(
+                "PPM rescale bits must be between {} and {}",
+                MIN_RESCALE_BITS, MAX_RESCALE_BITS
+            )));
+        }
+        if self.rescale_shift < MIN_RESCALE_SHIFT || self.rescale_shift > MAX_RESCALE_SHIFT {
+            return Err(ComprsError::InvalidTransform(format!(
+                "PPM rescale shift must be between {} and {}",
+                MIN_RESCALE_SHIFT, MAX_RESCALE_SHIFT
+            )));
+        }
+        if self.memory_bits < MIN_MEMORY_BITS || self.memory_bits > MAX_MEMORY_BITS {
+            return Err(ComprsError::InvalidTransform(format!(
+                "PPM memory bits must be between {} and {}",
+                MIN_MEMORY_BITS, MAX_MEMORY_BITS
+            )));
+        }
+        Ok(())
+    }
+
+    /// Returns the total at which context frequencies are rescaled
+    pub fn rescale_limit(&self) -> usize {
+        1 << self.rescale_bits
+    }
+
+    /// Returns the number of bytes the contexts of a model may use
+    pub fn memory_limit(&self) -> usize {
+        1 << self.memory_bits
+    }
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Params {
+            order: ORDER,
+            star: false,
+            escape_method: EscapeMethod::D,
+            rescale_bits: RESCALE_BITS,
+            rescale_shift: RESCALE_SHIFT,
+            memory_bits: MEMORY_BITS,
+            memory_policy: MemoryPolicy::Restart,
+            see: true,
+            coder: Backend::Arithmetic,
+        }
+    }
+}
+
+/// Prediction by partial matching with the given model parameters
+#[derive(Clone, Debug, Default)]
+pub struct Ppm {
+    pub params: Params,
+}
+
+impl Codec for Ppm {
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        apply(data, &self.params)
+    }
+
+    /// Restores data using the parameters stored in the encoded data
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        reduce(data)
+    }
+}
 
 /// Compress content provided by reader and write compressed data to writer.
-pub fn apply(data: &[u8]) -> Result<Vec<u8>> {
-    let mut encoder =
-        ArithmeticEncoder::new(BitWriter::new(Vec::with_capacity(data.len() / 4)), NUM_BITS);
-    let mut model = PPMModel::new(ORDER as u8, SYMBOL_LIMIT, ESCAPE_SYMBOL);
-    let mut history: Vec<Symbol> = Vec::with_capacity(model.order as usize);
+pub fn apply(data: &[u8], params: &Params) -> Result<Vec<u8>> {
+    params.validate()?;
+    let mut buffer = Vec::with_capacity(data.len() / 4);
+    bincode::serialize_into(&mut buffer, params)?;
+    bincode::serialize_into(&mut buffer, &(data.len() as u64))?;
+
+    match params.coder {
+        Backend::Arithmetic => {
+            let mut encoder = ArithmeticEncoder::new(BitWriter::new(buffer), NUM_BITS);
+            encode_data(data, params, &mut encoder)?;
+            encoder.finish()?;
+            Ok(encoder.inner_ref().clone())
+        }
+        Backend::Range => {
+            let mut encoder = RangeEncoder::new(buffer);
+            encode_data(data, params, &mut encoder)?;
+            encoder.finish()?;
+            Ok(encoder.inner_ref().clone())
+        }
+    }
+}
 
+fn encode_data<E: EntropyEncoder>(data: &[u8], params: &Params, encoder: &mut E) -> Result<()> {
+    let mut model = PPMModel::new(params, SYMBOL_LIMIT, data.len());
     for byte in data {
         let symbol = Symbol::from(*byte);
-        encode_symbol(&mut model, &history, symbol, &mut encoder)?;
-        model.increment_contexts(&history, symbol);
-
-        if model.order >= 1 {
-            mutate_history(symbol, model.order, &mut history);
-        }
+        encode_symbol(&mut model, symbol, encoder)?;
+        model.update(symbol);
     }
 
     // Encode EOF
-    encode_symbol(&mut model, &history, EOF, &mut encoder)?;
-    encoder.finish()?;
-    Ok(encoder.inner_ref().clone())
+    encode_symbol(&mut model, EOF, encoder)?;
+    Ok(())
 }
 
 /// Decompress content provided by reader and write restored data to writer.
-pub fn reduce(data: &[u8]) -> Result<Vec<u8>> {
-    let mut decoder = ArithmeticDecoder::new(BitReader::new(data), NUM_BITS)?;
-    let mut model = PPMModel::new(ORDER as u8, SYMBOL_LIMIT, ESCAPE_SYMBOL);
+pub fn reduce(mut data: &[u8]) -> Result<Vec<u8>> {
+    let params: Params = bincode::deserialize_from(&mut data)?;
+    let len: u64 = bincode::deserialize_from(&mut data)?;
+    params
+        .validate()
+        .map_err(|_| ComprsError::corrupt("invalid PPM parameters"))?;
 
-    let mut history: Vec<Symbol> = Vec::with_capacity(model.order as usize);
-    let mut buffer = Vec::with_capacity(data.len());
+    match params.coder {
+        Backend::Arithmetic => {
+            let mut decoder = ArithmeticDecoder::new(BitReader::new(data), NUM_BITS)
+                .map_err(|_| ComprsError::corrupt("truncated PPM data"))?;
+            decode_data(&params, len, data.len(), &mut decoder)
+        }
+        Backend::Range => {
+            let mut decoder =
+                RangeDecoder::new(data).map_err(|_| ComprsError::corrupt("truncated PPM data"))?;
+            decode_data(&params, len, data.len(), &mut decoder)
+        }
+    }
+}
+
+fn decode_data<D: EntropyDecoder>(
+    params: &Params,
+    len: u64,
+    capacity: usize,
+    decoder: &mut D,
+) -> Result<Vec<u8>> {
+    let mut model = PPMModel::new(params, SYMBOL_LIMIT, len as usize);
+    let mut buffer = Vec::with_capacity(capacity);
 
     loop {
-        let symbol = decode_symbol(&mut model, &history, &mut decoder)?;
+        let symbol = decode_symbol(&mut model, decoder)?;
         // Check if EOF symbol has occurred
         if symbol == EOF {
+            decoder.check_end()?;
             break;
         }
-        buffer.write_all(&[symbol as u8])?;
-        model.increment_contexts(&history, symbol);
-
-        if model.order >= 1 {
-            mutate_history(symbol, model.order, &mut history);
+        if buffer.len() as u64 == len {
+            return Err(ComprsError::corrupt("PPM data exceeds stored length"));
         }
+        buffer.write_all(&[symbol as u8])?;
+        model.update(symbol);
     }
-    Ok(buffer)
-}
 
-/// Append current symbol to history or shift back by one
-#[inline]
-fn mutate_history(symbol: Symbol, order: u8, history: &mut Vec<Symbol>) {
-    if history.len() >= order as usize {
-        history.remove(0);
+    if buffer.len() as u64 != len {
+        return Err(ComprsError::corrupt(
+            "PPM data is shorter than stored length",
+        ));
     }
-    history.push(symbol);
+    Ok(buffer)
 }
 
-/// Return highest order context that exists for the given history prefix.
-fn traverse_context<'a>(ctx: &'a mut Context, history: &[Symbol]) -> Option<&'a mut Context> {
-    if history.is_empty() {
-        return Some(ctx);
-    }
-
-    match ctx.sub_ctxs.get_mut(&history[0]) {
-        None => None,
-        Some(sub) => traverse_context(sub, &history[1..]),
+/// Adds all symbols which can be coded in the given context to the excluded set
+fn exclude_symbols(tree: &ContextTree, ctx: u32, excluded: &mut SymbolSet) {
+    for entry in tree.entries(ctx) {
+        if entry.count > 0 {
+            excluded.insert(entry.symbol);
+        }
     }
 }
 
-/// Try to use highest order context that exists based on the history suffix, such
-/// that the next symbol has non-zero frequency. When symbol 256 is produced at a context
+/// Try to use highest order context that exists for the current history, such
+/// that the next symbol has non-zero frequency. When the escape is produced at a context
 /// at any non-negative order, it means "escape to the next lower order with non-empty
 /// context". When symbol 256 is produced at the order -1 context, it means "EOF".
-fn encode_symbol<'a, W: Write>(
-    model: &'a mut PPMModel,
-    history: &[Symbol],
+/// Symbols of the contexts escaped from are excluded from the lower orders.
+fn encode_symbol<E: EntropyEncoder>(
+    model: &mut PPMModel,
     symbol: Symbol,
-    encoder: &mut ArithmeticEncoder<W>,
-) -> Result<()> {
-    let hist_len = history.len();
-    for order in (0..=hist_len).rev() {
-        match traverse_context(&mut model.context, &history[hist_len - order..hist_len]) {
-            None => {}
-            Some(ctx) => {
-                if symbol != EOF && ctx.frequencies.get(symbol) > 0 {
-                    return encoder.write(&mut ctx.frequencies, symbol);
-                }
-                // Else write context escape symbol and continue decrementing the order
-                encoder.write(&mut ctx.frequencies, EOF)?;
-            }
+    encoder: &mut E,
+) -> io::Result<()> {
+    model.excluded.clear();
+    let mut context = model.first_context();
+    while let Some((ctx, order)) = context {
+        let mut table = ContextTable::new(&model.tree, ctx, &model.excluded);
+        let see_key = model.see.as_ref().and_then(|see| {
+            see.apply(
+                &mut table,
+                order,
+                !model.excluded.is_empty(),
+                model.last_symbol,
+            )
+        });
+        let position = table.position(symbol);
+        if let (Some(see), Some(key)) = (model.see.as_mut(), see_key) {
+            see.update(key, position.is_none());
         }
+        if let Some(position) = position {
+            return encoder.write(&mut table, position);
+        }
+        // Else write context escape symbol and continue decrementing the order
+        let escape = table.escape();
+        encoder.write(&mut table, escape)?;
+        exclude_symbols(&model.tree, ctx, &mut model.excluded);
+        context = model.next_context(ctx, order);
     }
     // Logic for order = -1
-    encoder.write(&mut model.order_minus1_freqs, symbol)
+    let mut freqs = MaskedFrequencyTable::new(&model.order_minus1_freqs, &model.excluded);
+    encoder.write(&mut freqs, symbol)
 }
 
-/// Try to use highest order context that exists based on the history suffix. When symbol 256
+/// Try to use highest order context that exists for the current history. When the escape
 /// is consumed at a context at any non-negative order, it means "escape to the next lower order
 /// with non-empty context". When symbol 256 is consumed at the order -1 context, it means "EOF".
-fn decode_symbol<'a, R: Read>(
-    model: &'a mut PPMModel,
-    history: &[Symbol],
-    decoder: &mut ArithmeticDecoder<R>,
-) -> Result<Symbol> {
-    let hist_len = history.len();
-    for order in (0..=hist_len).rev() {
-        match traverse_context(&mut model.context, &history[hist_len - order..hist_len]) {
-            None => {}
-            Some(ref mut ctx) => {
-                let symbol = decoder.read(&mut ctx.frequencies)?;
-                if symbol < EOF {
-                    return Ok(symbol);
-                }
-                // Else we read the context escape symbol, so continue decrementing the order
-            }
+fn decode_symbol<D: EntropyDecoder>(model: &mut PPMModel, decoder: &mut D) -> io::Result<Symbol> {
+    model.excluded.clear();
+    let mut context = model.first_context();
+    while let Some((ctx, order)) = context {
+        let mut table = ContextTable::new(&model.tree, ctx, &model.excluded);
+        let see_key = model.see.as_ref().and_then(|see| {
+            see.apply(
+                &mut table,
+                order,
+                !model.excluded.is_empty(),
+                model.last_symbol,
+            )
+        });
+        let position = decoder.read(&mut table)?;
+        let symbol = table.symbol(position);
+        if let (Some(see), Some(key)) = (model.see.as_mut(), see_key) {
+            see.update(key, symbol.is_none());
         }
+        if let Some(symbol) = symbol {
+            return Ok(symbol);
+        }
+        // Else we read the context escape symbol, so continue decrementing the order
+        exclude_symbols(&model.tree, ctx, &mut model.excluded);
+        context = model.next_context(ctx, order);
     }
     // Logic for order = -1
-    decoder.read(&mut model.order_minus1_freqs)
+    let mut freqs = MaskedFrequencyTable::new(&model.order_minus1_freqs, &model.excluded);
+    decoder.read(&mut freqs)
 }
 
 #[cfg(test)]
 mod tests {
-    use std::io::Result;
+    use crate::error::Result;
 
-    use super::{apply, reduce};
+    use super::super::arithmetic_coder::Backend;
+    use super::model::PPMModel;
+    use super::{apply, reduce, EscapeMethod, MemoryPolicy, Params};
+    use super::{MAX_RESCALE_BITS, MAX_RESCALE_SHIFT, MIN_MEMORY_BITS, MIN_RESCALE_BITS};
 
     #[test]
     fn test_compression() -> Result<()> {
@@ -160,10 +376,207 @@ mod tests {
         )
         .into_bytes();
 
-        let intermediate = apply(&original)?;
-        let restored = reduce(&intermediate)?;
+        for escape_method in &[
+            EscapeMethod::A,
+            EscapeMethod::B,
+            EscapeMethod::C,
+            EscapeMethod::D,
+        ] {
+            for order in 0..=4 {
+                for (see, coder) in &[
+                    (false, Backend::Arithmetic),
+                    (true, Backend::Arithmetic),
+                    (true, Backend::Range),
+                ] {
+                    let params = Params {
+                        order,
+                        escape_method: *escape_method,
+                        see: *see,
+                        coder: *coder,
+                        ..Params::default()
+                    };
+                    let intermediate = apply(&original, &params)?;
+                    let restored = reduce(&intermediate)?;
+
+                    assert_eq!(original, restored);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_see() -> Result<()> {
+        let original: Vec<u8> = (0..50_000u32)
+            .flat_map(|i| format!("{} {} ", i % 97, i % 13).into_bytes())
+            .collect();
+
+        let params = Params {
+            order: 4,
+            ..Params::default()
+        };
+        let raw_params = Params {
+            see: false,
+            ..params.clone()
+        };
+        let compressed = apply(&original, &params)?;
+        assert_eq!(reduce(&compressed)?, original);
+        assert!(compressed.len() < apply(&original, &raw_params)?.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_rescale() -> Result<()> {
+        let original: Vec<u8> = (0..200_000u32)
+            .map(|i| {
+                if i < 100_000 {
+                    b'a' + (i % 3) as u8
+                } else {
+                    b'x'
+                }
+            })
+            .collect();
+
+        let params = Params {
+            order: 0,
+            ..Params::default()
+        };
+        let aged_params = Params {
+            rescale_bits: MIN_RESCALE_BITS,
+            ..params.clone()
+        };
+        let aged = apply(&original, &aged_params)?;
+        assert_eq!(reduce(&aged)?, original);
+        // Forgetting the first half makes the second half cheaper
+        assert!(aged.len() < apply(&original, &params)?.len());
 
-        assert_eq!(original, restored);
+        // Dividing by more at once follows frequent changes of the statistics better
+        let switching: Vec<u8> = (0..200_000u32)
+            .map(|i| {
+                if (i / 1000) % 2 == 0 {
+                    b'a' + (i % 3) as u8
+                } else {
+                    b'x' + (i % 2) as u8
+                }
+            })
+            .collect();
+        let halved = apply(&switching, &aged_params)?;
+        let forgetful_params = Params {
+            rescale_shift: 4,
+            ..aged_params
+        };
+        let forgetful = apply(&switching, &forgetful_params)?;
+        // The shift is read back from the stream
+        assert_eq!(reduce(&forgetful)?, switching);
+        assert!(forgetful.len() < halved.len());
+
+        let invalid = Params {
+            rescale_bits: MAX_RESCALE_BITS + 1,
+            ..Params::default()
+        };
+        assert!(apply(&original, &invalid).is_err());
+        for shift in &[0, MAX_RESCALE_SHIFT + 1] {
+            let invalid = Params {
+                rescale_shift: *shift,
+                ..Params::default()
+            };
+            assert!(apply(&original, &invalid).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_limit() -> Result<()> {
+        let original: Vec<u8> = (0..100_000u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 27) as u8 + b'a')
+            .collect();
+
+        for memory_policy in &[MemoryPolicy::Restart, MemoryPolicy::Freeze] {
+            let params = Params {
+                order: 6,
+                memory_bits: MIN_MEMORY_BITS,
+                memory_policy: *memory_policy,
+                ..Params::default()
+            };
+            let mut model = PPMModel::new(&params, 257, original.len());
+            for byte in &original {
+                model.update(u16::from(*byte));
+                assert!(model.tree.memory_usage() <= params.memory_limit() + 2 * 16 * 7);
+            }
+
+            let compressed = apply(&original, &params)?;
+            assert_eq!(reduce(&compressed)?, original);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_star() -> Result<()> {
+        let original: Vec<u8> = (0..2_000u32)
+            .flat_map(|i| {
+                format!(
+                    "{{\"id\": {}, \"level\": \"{}\", \"message\": \"request handled\"}}\n",
+                    i,
+                    ["info", "warn", "debug"][(i % 3) as usize]
+                )
+                .into_bytes()
+            })
+            .collect();
+
+        for escape_method in &[
+            EscapeMethod::A,
+            EscapeMethod::B,
+            EscapeMethod::C,
+            EscapeMethod::D,
+        ] {
+            let params = Params {
+                star: true,
+                escape_method: *escape_method,
+                ..Params::default()
+            };
+            let compressed = apply(&original, &params)?;
+            assert_eq!(reduce(&compressed)?, original);
+        }
+        // Long repeated structures are predicted from long contexts
+        let params = Params {
+            star: true,
+            ..Params::default()
+        };
+        let bounded = apply(&original, &Params::default())?;
+        assert!(apply(&original, &params)?.len() < bounded.len());
+
+        for memory_policy in &[MemoryPolicy::Restart, MemoryPolicy::Freeze] {
+            let params = Params {
+                star: true,
+                memory_bits: MIN_MEMORY_BITS,
+                memory_policy: *memory_policy,
+                ..Params::default()
+            };
+            let mut model = PPMModel::new(&params, 257, original.len());
+            for byte in &original {
+                model.update(u16::from(*byte));
+                assert!(model.tree.memory_usage() <= params.memory_limit() + 1024);
+            }
+
+            let compressed = apply(&original, &params)?;
+            assert_eq!(reduce(&compressed)?, original);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_star_corrupt_length() -> Result<()> {
+        let params = Params {
+            star: true,
+            ..Params::default()
+        };
+        let mut compressed = apply(b"unbounded contexts", &params)?;
+        // The stored length follows the parameters
+        let offset = bincode::serialize(&params).unwrap().len();
+        for byte in &mut compressed[offset..offset + 8] {
+            *byte = 0xff;
+        }
+        assert!(reduce(&compressed).is_err());
         Ok(())
     }
 }
@@ -1,71 +1,256 @@
-use super::super::arithmetic_coder::{FlatFrequencyTable, FrequencyTable, Symbol};
-use super::context::Context;
+use std::cmp;
+
+use super::super::arithmetic_coder::{FlatFrequencyTable, Symbol, SymbolSet};
+use super::super::MAX_CAPACITY;
+use super::context::{ContextTree, LEAF, NONE};
+use super::see::See;
+use super::{EscapeMethod, MemoryPolicy, Params, MAX_RESCALE_BITS};
 
 pub struct PPMModel {
-    pub context: Context,
+    pub tree: ContextTree,
     pub order: u8,
     pub order_minus1_freqs: FlatFrequencyTable,
-    symbol_limit: Symbol,
-    escape_symbol: Symbol,
+    /// Contexts of the current history, indexed by their order
+    pub contexts: Vec<u32>,
+    /// Unbounded model, see `update_star`
+    star: bool,
+    /// Context to start coding at in an unbounded model, along with its order
+    current: u32,
+    depth: usize,
+    /// Symbols coded so far, only kept by unbounded models
+    history: Vec<Symbol>,
+    /// Symbols ruled out by the higher orders while coding the current symbol
+    pub excluded: SymbolSet,
+    /// Secondary escape estimation, if enabled
+    pub see: Option<See>,
+    pub last_symbol: Symbol,
+    escape_method: EscapeMethod,
+    rescale_limit: u32,
+    rescale_shift: u32,
+    memory_policy: MemoryPolicy,
+    frozen: bool,
 }
 
 impl PPMModel {
-    pub fn new(order: u8, symbol_limit: Symbol, escape_symbol: Symbol) -> Self {
-        debug_assert!(escape_symbol < symbol_limit);
+    /// Creates a model for coding `len` symbols
+    pub fn new(params: &Params, symbol_limit: Symbol, len: usize) -> Self {
+        let order = params.order as usize;
+        let nodes = if params.star { 2 } else { order + 1 };
+        let capacity = len.saturating_add(1).saturating_mul(nodes);
+        let mut contexts = vec![NONE; order + 1];
+        contexts[0] = ContextTree::ROOT;
 
-        let mut context = Context::new(symbol_limit);
-        context.frequencies.increment(escape_symbol);
         PPMModel {
+            tree: ContextTree::new(params.memory_limit(), capacity),
+            order: params.order,
             order_minus1_freqs: FlatFrequencyTable::new(symbol_limit),
-            order,
-            symbol_limit,
-            escape_symbol,
-            context,
+            contexts,
+            star: params.star,
+            current: ContextTree::ROOT,
+            depth: 0,
+            history: Vec::with_capacity(if params.star {
+                cmp::min(len, MAX_CAPACITY)
+            } else {
+                0
+            }),
+            excluded: SymbolSet::new(symbol_limit),
+            see: if params.see {
+                Some(See::new(1 << MAX_RESCALE_BITS))
+            } else {
+                None
+            },
+            last_symbol: 0,
+            escape_method: params.escape_method,
+            rescale_limit: params.rescale_limit() as u32,
+            rescale_shift: u32::from(params.rescale_shift),
+            memory_policy: params.memory_policy,
+            frozen: false,
         }
     }
 
-    pub fn increment_contexts(&mut self, history: &[Symbol], symbol: Symbol) {
-        let hist_len = history.len();
+    /// Returns the first context to code a symbol in, along with its order
+    pub fn first_context(&self) -> Option<(u32, usize)> {
+        if self.star {
+            Some((self.current, self.depth))
+        } else {
+            self.next_context(NONE, self.contexts.len())
+        }
+    }
+
+    /// Returns the context to escape to from a context of the given order
+    pub fn next_context(&self, ctx: u32, order: usize) -> Option<(u32, usize)> {
+        if self.star {
+            match self.tree.context(ctx).suffix {
+                NONE => None,
+                suffix => Some((suffix, order - 1)),
+            }
+        } else {
+            (0..order)
+                .rev()
+                .find(|o| self.contexts[*o] != NONE)
+                .map(|o| (self.contexts[o], o))
+        }
+    }
 
-        debug_assert!(hist_len <= self.order as usize);
-        debug_assert!(symbol < self.symbol_limit);
+    /// Updates the contexts of the current history with `symbol` and moves on to the
+    /// contexts following it. Once the memory limit is reached, the model is either
+    /// restarted or frozen, depending on the memory policy.
+    pub fn update(&mut self, symbol: Symbol) {
+        self.last_symbol = symbol;
+        if !self.frozen && self.tree.is_full() {
+            match self.memory_policy {
+                MemoryPolicy::Restart => {
+                    self.tree.reset();
+                    for ctx in self.contexts.iter_mut() {
+                        *ctx = NONE;
+                    }
+                    self.contexts[0] = ContextTree::ROOT;
+                    self.current = ContextTree::ROOT;
+                    self.depth = 0;
+                }
+                MemoryPolicy::Freeze => self.frozen = true,
+            }
+        }
+        if self.star {
+            return self.update_star(symbol);
+        }
 
-        for order in 0..=hist_len {
-            populate_contexts(
-                &mut self.context,
-                &history[hist_len - order..hist_len],
-                symbol,
-                self.escape_symbol,
-                self.symbol_limit,
-            );
+        let order = self.order as usize;
+        for o in (0..=order).rev() {
+            let ctx = self.contexts[o];
+            let entry = match ctx {
+                NONE => None,
+                _ => self.update_context(ctx, symbol),
+            };
+            if o < order {
+                self.contexts[o + 1] = match entry {
+                    Some(entry) => self.tree.child(entry, !self.frozen),
+                    None => NONE,
+                };
+            }
         }
     }
-}
 
-fn populate_contexts(
-    ctx: &mut Context,
-    history: &[Symbol],
-    symbol: Symbol,
-    escape_symbol: Symbol,
-    symbol_limit: Symbol,
-) {
-    if history.is_empty() {
-        ctx.frequencies.increment(symbol);
-        return;
+    /// Updates an unbounded model as in PPM*. Only the contexts escaped from and the context
+    /// the symbol was found in are updated. A context followed by a symbol for the first time
+    /// is not created, the symbol entry points into the history instead. Such a context is
+    /// deterministic and is created once it occurs again. Coding starts at the shortest
+    /// deterministic context of the history, or else at the longest one.
+    fn update_star(&mut self, symbol: Symbol) {
+        self.history.push(symbol);
+        let leaf = match self.history.len() as u32 {
+            next if next < LEAF => LEAF | next,
+            _ => NONE,
+        };
+
+        let (mut ctx, mut depth) = (self.current, self.depth);
+        let found = loop {
+            let known = self.tree.find(ctx, symbol).is_some();
+            match self.update_context(ctx, symbol) {
+                Some(entry) if known => break Some(entry),
+                Some(entry) => self.tree.set_child(entry, leaf),
+                None => {}
+            }
+            if ctx == ContextTree::ROOT {
+                break None;
+            }
+            ctx = self.tree.context(ctx).suffix;
+            depth -= 1;
+        };
+
+        let (mut ctx, mut depth) = match found {
+            Some(entry) => self.successor(ctx, entry, symbol, depth),
+            None => (ContextTree::ROOT, 0),
+        };
+        while ctx != ContextTree::ROOT && self.tree.context(ctx).len == 1 {
+            let suffix = self.tree.context(ctx).suffix;
+            if self.tree.context(suffix).len != 1 {
+                break;
+            }
+            ctx = suffix;
+            depth -= 1;
+        }
+        self.current = ctx;
+        self.depth = depth;
     }
 
-    let sym = history[0];
-    if ctx.sub_ctxs.get(&sym).is_none() {
-        let mut sub_ctx = Context::new(symbol_limit);
-        sub_ctx.frequencies.increment(escape_symbol);
-        ctx.sub_ctxs.insert(sym, sub_ctx);
+    /// Returns the context following `symbol` in a context of the given order, along with
+    /// its order. Contexts which point into the history are created, including their
+    /// missing suffixes. A frozen model returns the longest existing context instead.
+    fn successor(
+        &mut self,
+        mut ctx: u32,
+        entry: u32,
+        symbol: Symbol,
+        mut depth: usize,
+    ) -> (u32, usize) {
+        let mut entry = Some(entry);
+        let mut missing = Vec::new();
+        let (mut successor, mut depth) = loop {
+            let child = entry.map_or(NONE, |entry| self.tree.entry(entry).child);
+            if child != NONE && child & LEAF == 0 {
+                break (child, depth + 1);
+            }
+            if !self.frozen {
+                let entry = entry.unwrap_or_else(|| self.tree.add_entry(ctx, symbol));
+                missing.push((entry, child));
+            }
+            if ctx == ContextTree::ROOT {
+                break (ContextTree::ROOT, 0);
+            }
+            ctx = self.tree.context(ctx).suffix;
+            depth -= 1;
+            entry = self.tree.find(ctx, symbol);
+        };
+
+        for (entry, child) in missing.into_iter().rev() {
+            let ctx = self.tree.add_context();
+            self.tree.set_suffix(ctx, successor);
+            if child != NONE {
+                // The context occurred once, followed by the symbol at this position
+                let position = (child & !LEAF) as usize;
+                if let Some(next) = self.update_context(ctx, self.history[position]) {
+                    let leaf = if child + 1 < NONE { child + 1 } else { NONE };
+                    self.tree.set_child(next, leaf);
+                }
+            }
+            self.tree.set_child(entry, ctx);
+            successor = ctx;
+            depth += 1;
+        }
+        (successor, depth)
     }
 
-    populate_contexts(
-        ctx.sub_ctxs.get_mut(&sym).unwrap(),
-        &history[1..],
-        symbol,
-        escape_symbol,
-        symbol_limit,
-    );
+    /// Updates the symbol and escape counts of a context according to the escape method
+    /// and returns the entry of the symbol. Every context starts with an escape count of
+    /// one, which grows with the number of distinct symbols for the methods B, C and D.
+    fn update_context(&mut self, ctx: u32, symbol: Symbol) -> Option<u32> {
+        let (entry, first) = match self.tree.find(ctx, symbol) {
+            Some(entry) => (entry, false),
+            None if self.frozen => return None,
+            None => (self.tree.add_entry(ctx, symbol), true),
+        };
+        if first && self.escape_method != EscapeMethod::A && self.tree.context(ctx).len > 1 {
+            self.tree.add_escape(ctx, 1);
+        }
+
+        let count = match self.escape_method {
+            // Symbol counts c(s), escape count 1
+            EscapeMethod::A => 1,
+            // Symbol counts c(s) - 1, escape count q
+            EscapeMethod::B if first => 0,
+            EscapeMethod::B => 1,
+            // Symbol counts c(s), escape count q
+            EscapeMethod::C => 1,
+            // Symbol counts 2c(s) - 1, escape count q
+            EscapeMethod::D if first => 1,
+            EscapeMethod::D => 2,
+        };
+        let entry = self.tree.add_count(ctx, entry, count);
+
+        if self.tree.context(ctx).total > self.rescale_limit {
+            self.tree.rescale(ctx, self.rescale_shift);
+        }
+        Some(entry)
+    }
 }
@@ -0,0 +1,89 @@
+use super::super::arithmetic_coder::{FrequencyTable, Symbol};
+use super::context::ContextTable;
+
+// Secondary escape estimation as in PPMZ and PPMd: the escape frequency of a context is
+// replaced by adaptive statistics shared by all contexts with similar properties.
+
+/// Number of observations the raw escape estimate of a context is worth
+const PRIOR: u64 = 16;
+/// Total of a cell at which its counts are halved
+const CELL_LIMIT: u16 = 1 << 8;
+const PROBABILITY_BITS: u32 = 16;
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Cell {
+    escapes: u16,
+    total: u16,
+}
+
+pub struct See {
+    cells: Vec<Cell>,
+    max_total: usize,
+}
+
+impl See {
+    /// Creates empty statistics, estimates keep the totals of a table within `max_total`
+    pub fn new(max_total: usize) -> Self {
+        See {
+            cells: vec![Cell::default(); 1 << 16],
+            max_total,
+        }
+    }
+
+    /// Returns the cell of a context from its order, its number of symbols, whether
+    /// symbols are excluded, the magnitude of its raw escape probability and the last
+    /// coded symbol. Symbols above 15 share a bucket, which suits the output of MTF.
+    fn key(order: usize, len: usize, excluded: bool, raw: u64, last_symbol: Symbol) -> usize {
+        let order = order.min(7);
+        let len = if len <= 4 {
+            len
+        } else {
+            (3 + len.ilog2() as usize).min(15)
+        };
+        let raw = ((1u64 << PROBABILITY_BITS) / raw.max(1)).ilog2().min(15) as usize;
+        let last = (last_symbol as usize).min(15);
+        order << 13 | len << 9 | (excluded as usize) << 8 | raw << 4 | last
+    }
+
+    /// Replaces the escape frequency of the table by the estimate of its cell.
+    /// Returns the cell to update, or None if the table only holds the escape.
+    pub fn apply(
+        &self,
+        table: &mut ContextTable,
+        order: usize,
+        excluded: bool,
+        last_symbol: Symbol,
+    ) -> Option<usize> {
+        let symbols = table.symbol_total() as u64;
+        if symbols == 0 {
+            return None;
+        }
+        // Blend the cell statistics with the raw estimate of the context
+        let one = 1u64 << PROBABILITY_BITS;
+        let raw = table.get(table.escape()) as u64;
+        let raw = raw * one / (symbols + raw);
+        let key = See::key(order, table.escape() as usize, excluded, raw, last_symbol);
+        let cell = self.cells[key];
+        let probability =
+            (u64::from(cell.escapes) * one + raw * PRIOR) / (u64::from(cell.total) + PRIOR);
+        let probability = probability.max(1).min(one - 1);
+
+        let escape = (symbols * probability / (one - probability)) as usize;
+        let max_escape = self.max_total.saturating_sub(symbols as usize).max(1);
+        table.set_escape(escape.max(1).min(max_escape));
+        Some(key)
+    }
+
+    /// Records whether the context of a cell was escaped from
+    pub fn update(&mut self, key: usize, escaped: bool) {
+        let cell = &mut self.cells[key];
+        if escaped {
+            cell.escapes += 1;
+        }
+        cell.total += 1;
+        if cell.total > CELL_LIMIT {
+            cell.escapes = cell.escapes.div_ceil(2);
+            cell.total = cell.total.div_ceil(2);
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use std::cmp;
+
+use super::ppm::EscapeMethod;
+use super::Transform;
+use super::{bwt, lipt, lz77, mtf, ppm, rans};
+
+/// Highest supported compression level
+pub const MAX_LEVEL: u8 = 6;
+
+/// Compression level used if none is specified
+pub const DEFAULT_LEVEL: u8 = 3;
+
+/// Encoding parameters selected by a compression level
+#[derive(Clone, Debug, PartialEq)]
+pub struct Preset {
+    /// Transforms applied to each block, in order
+    pub transforms: Vec<Transform>,
+    /// Size of the chunks sorted by the BWT
+    pub bwt_chunk_size: usize,
+    /// Model parameters of the PPM stage
+    pub ppm: ppm::Params,
+    /// Frequency mode of the rANS stage
+    pub rans: rans::Mode,
+    /// Match finder parameters of the LZ77 stage
+    pub lz77: lz77::Params,
+    /// Dictionary of the LIPT stage
+    pub lipt: lipt::Dictionary,
+    /// Parameters of the sticky MTF and WFC stages
+    pub mtf: mtf::variants::Params,
+}
+
+impl Preset {
+    /// Returns the preset for the given level, ranging from 0 (fastest) to `MAX_LEVEL` (strongest).
+    /// Higher levels select the strongest preset.
+    pub fn from_level(level: u8) -> Self {
+        let level = cmp::min(level, MAX_LEVEL);
+        // Orders rise with the level but stay low, as BWT output gains little from long contexts.
+        // Escape method B pays off at the higher orders, where many symbols are novel.
+        // The strongest level models the text directly with unbounded contexts.
+        let (bwt_chunk_size, use_rle, order, escape_method) = match level {
+            0 => (256 * 1024, true, 1, EscapeMethod::C),
+            1 => (512 * 1024, false, 1, EscapeMethod::D),
+            2 => (bwt::CHUNK_SIZE, false, 2, EscapeMethod::D),
+            3 => (2 * 1024 * 1024, false, 2, EscapeMethod::B),
+            4 => (4 * 1024 * 1024, false, 3, EscapeMethod::B),
+            5 => (8 * 1024 * 1024, false, 4, EscapeMethod::B),
+            _ => (8 * 1024 * 1024, false, 4, EscapeMethod::D),
+        };
+        let star = level == MAX_LEVEL;
+
+        let mut transforms = Vec::new();
+        if !star {
+            transforms.extend_from_slice(&[Transform::BWT, Transform::MTF]);
+        }
+        if use_rle {
+            transforms.push(Transform::RLE);
+        }
+        transforms.push(Transform::PPM);
+
+        Preset {
+            transforms,
+            bwt_chunk_size,
+            ppm: ppm::Params {
+                order,
+                star,
+                escape_method,
+                ..ppm::Params::default()
+            },
+            rans: rans::Mode::default(),
+            lz77: lz77::Params::default(),
+            lipt: lipt::Dictionary::default(),
+            mtf: mtf::variants::Params::default(),
+        }
+    }
+
+    /// Replaces the transforms of the preset
+    pub fn with_transforms(mut self, transforms: Vec<Transform>) -> Self {
+        self.transforms = transforms;
+        self
+    }
+}
+
+impl Default for Preset {
+    fn default() -> Self {
+        Preset::from_level(DEFAULT_LEVEL)
+    }
+}
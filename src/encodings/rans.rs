@@ -0,0 +1,473 @@
+use std::cmp;
+use std::fmt;
+use std::io::Cursor;
+use std::str::FromStr;
+
+use varuint::{Deserializable, Serializable, Varint};
+
+use crate::codec::Codec;
+use crate::encodings::arithmetic_coder::{
+    FrequencyTable, FrequencyTableMut, SimpleFrequencyTable, Symbol,
+};
+use crate::encodings::MAX_CAPACITY;
+use crate::error::{ComprsError, Result};
+
+/// Exponent of the total of the normalized frequency tables
+const SCALE_BITS: u32 = 15;
+/// Lower bound of the coder states, they are kept within [L, 256 L)
+const RANS_L: u32 = 1 << 23;
+/// Number of interleaved states, symbol i is coded with state i % LANES
+const LANES: usize = 4;
+const NUM_SYMBOLS: Symbol = 256;
+/// Total at which the adaptive counts are halved
+const RESCALE_LIMIT: usize = 1 << 16;
+/// Number of symbols after which the adaptive model is normalized for the first time.
+/// The interval doubles after each update, up to `MAX_INTERVAL`.
+const MIN_INTERVAL: usize = 256;
+const MAX_INTERVAL: usize = 4096;
+
+/// How the symbol frequencies are obtained
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Mode {
+    /// Frequencies of the whole input, stored in front of the coded data
+    #[default]
+    Static,
+    /// Frequencies learned while coding, renormalized at growing intervals
+    Adaptive,
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let printable = match *self {
+            Mode::Static => "static",
+            Mode::Adaptive => "adaptive",
+        };
+        write!(f, "{}", printable)
+    }
+}
+
+impl FromStr for Mode {
+    type Err = ComprsError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "static" => Ok(Mode::Static),
+            "adaptive" => Ok(Mode::Adaptive),
+            _ => Err(ComprsError::InvalidTransform(format!(
+                "unknown rANS mode '{}'",
+                s
+            ))),
+        }
+    }
+}
+
+/// Order-0 rANS coder for bytes
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Rans {
+    pub mode: Mode,
+}
+
+impl Codec for Rans {
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        apply(data, self.mode)
+    }
+
+    /// Restores data using the mode stored in the encoded data
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        reduce(data)
+    }
+}
+
+/// Frequency table scaled to a total of `1 << SCALE_BITS`, with a lookup from
+/// cumulative frequencies to symbols for decoding
+pub struct RansTable {
+    frequencies: Vec<u32>,
+    starts: Vec<u32>,
+    slots: Vec<u8>,
+}
+
+impl RansTable {
+    /// Creates a table with the same frequency for all symbols
+    pub fn uniform(num_symbols: Symbol) -> Self {
+        let mut uniform = SimpleFrequencyTable::new(num_symbols);
+        for symbol in 0..num_symbols {
+            uniform.set(symbol, 1);
+        }
+        RansTable::normalize(&uniform)
+    }
+
+    /// Scales the frequencies of a table, every symbol with a non-zero frequency keeps
+    /// a frequency of at least one
+    pub fn normalize<T: FrequencyTable>(table: &T) -> Self {
+        let total = table.total().max(1) as u64;
+        let mut frequencies: Vec<u32> = (0..table.get_symbol_limit())
+            .map(|symbol| match table.get(symbol) as u64 {
+                0 => 0,
+                frequency => ((frequency << SCALE_BITS) / total).max(1) as u32,
+            })
+            .collect();
+
+        // Give the rounding error to the most frequent symbols
+        let target = 1u32 << SCALE_BITS;
+        let mut sum: u32 = frequencies.iter().sum();
+        while sum != target {
+            let largest = (0..frequencies.len())
+                .max_by_key(|i| frequencies[*i])
+                .unwrap();
+            if sum < target {
+                frequencies[largest] += target - sum;
+                sum = target;
+            } else {
+                let excess = (sum - target).min(frequencies[largest] - 1);
+                frequencies[largest] -= excess;
+                sum -= excess;
+            }
+        }
+        RansTable::from_frequencies(frequencies)
+    }
+
+    fn from_frequencies(frequencies: Vec<u32>) -> Self {
+        let mut starts = Vec::with_capacity(frequencies.len());
+        let mut slots = Vec::with_capacity(1 << SCALE_BITS);
+        for (symbol, frequency) in frequencies.iter().enumerate() {
+            starts.push(slots.len() as u32);
+            slots.extend((0..*frequency).map(|_| symbol as u8));
+        }
+        RansTable {
+            frequencies,
+            starts,
+            slots,
+        }
+    }
+
+    fn serialize(&self, buffer: &mut Vec<u8>) -> Result<()> {
+        for frequency in &self.frequencies {
+            Varint(*frequency).serialize(buffer)?;
+        }
+        Ok(())
+    }
+
+    fn deserialize(reader: &mut Cursor<&[u8]>) -> Result<Self> {
+        let mut frequencies = Vec::with_capacity(NUM_SYMBOLS as usize);
+        for _ in 0..NUM_SYMBOLS {
+            let frequency: Varint<u32> = Varint::deserialize(reader)
+                .map_err(|_| ComprsError::corrupt("truncated rANS frequency table"))?;
+            frequencies.push(frequency.0);
+        }
+        if frequencies.iter().map(|f| u64::from(*f)).sum::<u64>() != 1 << SCALE_BITS {
+            return Err(ComprsError::corrupt("invalid rANS frequency table"));
+        }
+        Ok(RansTable::from_frequencies(frequencies))
+    }
+}
+
+impl FrequencyTable for RansTable {
+    #[inline]
+    fn get(&self, symbol: Symbol) -> usize {
+        self.frequencies[symbol as usize] as usize
+    }
+
+    #[inline]
+    fn get_low(&self, symbol: Symbol) -> usize {
+        self.starts[symbol as usize] as usize
+    }
+
+    #[inline]
+    fn get_high(&self, symbol: Symbol) -> usize {
+        (self.starts[symbol as usize] + self.frequencies[symbol as usize]) as usize
+    }
+
+    #[inline]
+    fn get_symbol_limit(&self) -> Symbol {
+        self.frequencies.len() as Symbol
+    }
+
+    #[inline]
+    fn total(&self) -> usize {
+        1 << SCALE_BITS
+    }
+
+    #[inline]
+    fn find(&self, value: usize) -> Symbol {
+        Symbol::from(self.slots[value])
+    }
+}
+
+/// Interleaved rANS encoder. Symbols are written in reverse order, the output is
+/// read forwards by `RansDecoder`.
+pub struct RansEncoder {
+    states: [u32; LANES],
+    // Output in reverse order
+    buffer: Vec<u8>,
+    remaining: usize,
+}
+
+impl RansEncoder {
+    /// Creates an encoder for `len` symbols
+    pub fn new(len: usize) -> Self {
+        RansEncoder {
+            states: [RANS_L; LANES],
+            buffer: Vec::with_capacity(len / 2),
+            remaining: len,
+        }
+    }
+
+    /// Writes the symbol preceding the previously written one
+    pub fn write<T: FrequencyTable>(&mut self, freqtable: &T, symbol: Symbol) {
+        debug_assert_eq!(freqtable.total(), 1 << SCALE_BITS);
+        let start = freqtable.get_low(symbol) as u32;
+        let frequency = freqtable.get(symbol) as u32;
+        self.put(start, frequency);
+    }
+
+    fn put(&mut self, start: u32, frequency: u32) {
+        debug_assert!(frequency > 0, "symbol has zero frequency");
+        debug_assert!(self.remaining > 0);
+        self.remaining -= 1;
+        let lane = self.remaining % LANES;
+
+        let mut x = self.states[lane];
+        let x_max = ((RANS_L >> SCALE_BITS) << 8) * frequency;
+        while x >= x_max {
+            self.buffer.push(x as u8);
+            x >>= 8;
+        }
+        self.states[lane] = ((x / frequency) << SCALE_BITS) + (x % frequency) + start;
+    }
+
+    /// Writes the final states and returns the coded data
+    pub fn finish(mut self) -> Vec<u8> {
+        for state in self.states.iter().rev() {
+            self.buffer.extend_from_slice(&state.to_le_bytes());
+        }
+        self.buffer.reverse();
+        self.buffer
+    }
+}
+
+pub struct RansDecoder<'a> {
+    states: [u32; LANES],
+    data: &'a [u8],
+    position: usize,
+    count: usize,
+}
+
+impl<'a> RansDecoder<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self> {
+        if data.len() < 4 * LANES {
+            return Err(ComprsError::corrupt("truncated rANS data"));
+        }
+        let mut states = [0; LANES];
+        for (lane, state) in states.iter_mut().enumerate() {
+            let mut bytes = [0; 4];
+            bytes.copy_from_slice(&data[4 * lane..4 * lane + 4]);
+            *state = u32::from_be_bytes(bytes);
+            if *state < RANS_L || *state >= RANS_L << 8 {
+                return Err(ComprsError::corrupt("invalid rANS state"));
+            }
+        }
+        Ok(RansDecoder {
+            states,
+            data,
+            position: 4 * LANES,
+            count: 0,
+        })
+    }
+
+    pub fn read<T: FrequencyTable>(&mut self, freqtable: &T) -> Result<Symbol> {
+        let lane = self.count % LANES;
+        self.count += 1;
+
+        let mut x = self.states[lane];
+        let slot = x & ((1 << SCALE_BITS) - 1);
+        let symbol = freqtable.find(slot as usize);
+        let start = freqtable.get_low(symbol) as u32;
+        let frequency = freqtable.get(symbol) as u32;
+        x = frequency * (x >> SCALE_BITS) + slot - start;
+
+        while x < RANS_L {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or_else(|| ComprsError::corrupt("truncated rANS data"))?;
+            x = x << 8 | u32::from(byte);
+            self.position += 1;
+        }
+        self.states[lane] = x;
+        Ok(symbol)
+    }
+
+    /// Checks that all data was consumed and the states are back at their initial value
+    pub fn finish(&self) -> Result<()> {
+        if self.position != self.data.len() || self.states.iter().any(|x| *x != RANS_L) {
+            return Err(ComprsError::corrupt("rANS data does not end properly"));
+        }
+        Ok(())
+    }
+}
+
+/// Adaptive order-0 model, the coded frequencies are renormalized from the counts at
+/// growing intervals
+struct AdaptiveModel {
+    counts: SimpleFrequencyTable,
+    table: RansTable,
+    interval: usize,
+    next_update: usize,
+    position: usize,
+}
+
+impl AdaptiveModel {
+    fn new() -> Self {
+        let mut counts = SimpleFrequencyTable::new(NUM_SYMBOLS);
+        for symbol in 0..NUM_SYMBOLS {
+            counts.set(symbol, 1);
+        }
+        AdaptiveModel {
+            table: RansTable::normalize(&counts),
+            counts,
+            interval: MIN_INTERVAL,
+            next_update: MIN_INTERVAL,
+            position: 0,
+        }
+    }
+
+    fn update(&mut self, symbol: Symbol) {
+        self.counts.increment_limited(symbol, RESCALE_LIMIT);
+        self.position += 1;
+        if self.position == self.next_update {
+            self.table = RansTable::normalize(&self.counts);
+            self.interval = (self.interval * 2).min(MAX_INTERVAL);
+            self.next_update += self.interval;
+        }
+    }
+}
+
+pub fn apply(data: &[u8], mode: Mode) -> Result<Vec<u8>> {
+    let mut buffer = Vec::with_capacity(data.len() / 2 + 16);
+    bincode::serialize_into(&mut buffer, &mode)?;
+    bincode::serialize_into(&mut buffer, &(data.len() as u64))?;
+
+    let mut encoder = RansEncoder::new(data.len());
+    match mode {
+        Mode::Static => {
+            let mut counts = SimpleFrequencyTable::new(NUM_SYMBOLS);
+            for byte in data {
+                counts.increment(Symbol::from(*byte));
+            }
+            let table = RansTable::normalize(&counts);
+            table.serialize(&mut buffer)?;
+            for byte in data.iter().rev() {
+                encoder.write(&table, Symbol::from(*byte));
+            }
+        }
+        Mode::Adaptive => {
+            // The model is only known forwards, so the coded ranges are collected first
+            let mut model = AdaptiveModel::new();
+            let ranges: Vec<(u16, u16)> = data
+                .iter()
+                .map(|byte| {
+                    let symbol = Symbol::from(*byte);
+                    let range = (
+                        model.table.get_low(symbol) as u16,
+                        model.table.get(symbol) as u16,
+                    );
+                    model.update(symbol);
+                    range
+                })
+                .collect();
+            for (start, frequency) in ranges.into_iter().rev() {
+                encoder.put(u32::from(start), u32::from(frequency));
+            }
+        }
+    }
+    buffer.extend_from_slice(&encoder.finish());
+    Ok(buffer)
+}
+
+pub fn reduce(mut data: &[u8]) -> Result<Vec<u8>> {
+    let mode: Mode = bincode::deserialize_from(&mut data)?;
+    let len: u64 = bincode::deserialize_from(&mut data)?;
+
+    let mut reader = Cursor::new(data);
+    let table = match mode {
+        Mode::Static => RansTable::deserialize(&mut reader)?,
+        Mode::Adaptive => RansTable::uniform(NUM_SYMBOLS),
+    };
+    let mut decoder = RansDecoder::new(&data[reader.position() as usize..])?;
+
+    let mut decoded = Vec::with_capacity(cmp::min(len as usize, MAX_CAPACITY));
+    match mode {
+        Mode::Static => {
+            for _ in 0..len {
+                decoded.push(decoder.read(&table)? as u8);
+            }
+        }
+        Mode::Adaptive => {
+            let mut model = AdaptiveModel::new();
+            for _ in 0..len {
+                let symbol = decoder.read(&model.table)?;
+                decoded.push(symbol as u8);
+                model.update(symbol);
+            }
+        }
+    }
+    decoder.finish()?;
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::encodings::arithmetic_coder::{
+        FrequencyTable, FrequencyTableMut, SimpleFrequencyTable,
+    };
+    use crate::encodings::mtf;
+
+    use super::{apply, reduce, Mode, RansTable, SCALE_BITS};
+
+    #[test]
+    fn test_normalize() {
+        let mut counts = SimpleFrequencyTable::new(256);
+        counts.set(0, 1_000_000);
+        counts.set(7, 1);
+        counts.set(255, 3);
+        let table = RansTable::normalize(&counts);
+        assert_eq!(table.total(), 1 << SCALE_BITS);
+        assert_eq!(table.get(7), 1);
+        assert!(table.get(255) >= 1);
+        assert_eq!(table.get(1), 0);
+        assert_eq!(table.get_low(255), (1 << SCALE_BITS) - table.get(255));
+        assert_eq!(table.find(table.get_low(7)), 7);
+    }
+
+    #[test]
+    fn test_modes() {
+        let text = "Lorem ipsum dolor sit amet, consectetur adipiscing elit. ".repeat(40);
+        let inputs: Vec<Vec<u8>> = vec![
+            Vec::new(),
+            vec![42],
+            vec![7; 1000],
+            text.clone().into_bytes(),
+            mtf::apply(text.as_bytes()),
+            (0..=255).collect(),
+        ];
+        for mode in &[Mode::Static, Mode::Adaptive] {
+            for data in &inputs {
+                let encoded = apply(data, *mode).unwrap();
+                assert_eq!(&reduce(&encoded).unwrap(), data);
+                if data.len() > 1000 {
+                    assert!(encoded.len() < data.len() * 3 / 4);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let data = "abracadabra".repeat(100).into_bytes();
+        for mode in &[Mode::Static, Mode::Adaptive] {
+            let encoded = apply(&data, *mode).unwrap();
+            assert!(reduce(&encoded[..encoded.len() - 1]).is_err());
+            assert!(reduce(&encoded[..20]).is_err());
+        }
+    }
+}
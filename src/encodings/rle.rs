@@ -1,9 +1,29 @@
-use std::io::{Cursor, Read, Result, Seek, Write};
+use std::io::{Cursor, Read, Seek, Write};
 
 use varuint::{Deserializable, Serializable, Varint};
 
+use crate::codec::Codec;
+use crate::error::{ComprsError, Result};
+
+/// Run-length encoding into varint count and symbol pairs
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Rle;
+
+impl Codec for Rle {
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        apply(data)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        reduce(data)
+    }
+}
+
 pub fn apply(data: &[u8]) -> Result<Vec<u8>> {
     let len = data.len();
+    if len == 0 {
+        return Ok(Vec::new());
+    }
     debug!("DEBUG:RLE: before: {}", len);
     let mut writer = Cursor::new(Vec::with_capacity(data.len()));
     let mut pair = RLEPair::new(1, data[0]);
@@ -25,17 +45,19 @@ pub fn apply(data: &[u8]) -> Result<Vec<u8>> {
     Ok(encoded)
 }
 
-pub fn reduce(data: &[u8]) -> Vec<u8> {
+pub fn reduce(data: &[u8]) -> Result<Vec<u8>> {
     let mut decoded: Vec<u8> = Vec::with_capacity(data.len());
 
     let mut reader = Cursor::new(data);
-    while let Ok(pair) = RLEPair::deserialize(&mut reader) {
+    while (reader.position() as usize) < data.len() {
+        let pair = RLEPair::deserialize(&mut reader)
+            .map_err(|_| ComprsError::corrupt("truncated RLE pair"))?;
         for _ in 0..pair.count.0 {
             decoded.push(pair.symbol);
         }
     }
 
-    decoded
+    Ok(decoded)
 }
 
 struct RLEPair {
@@ -56,10 +78,9 @@ impl RLEPair {
     }
 
     pub fn serialize<W: Write>(&self, writer: &mut W) -> Result<()> {
-        self.count
-            .serialize(writer)
-            .expect("unable to serialize varuint");
-        writer.write_all(&[self.symbol])
+        self.count.serialize(writer)?;
+        writer.write_all(&[self.symbol])?;
+        Ok(())
     }
 
     pub fn deserialize<R: Read + Seek>(reader: &mut R) -> Result<Self> {
@@ -91,9 +112,20 @@ mod tests {
     fn test_reduce() {
         let data = [12, 87, 1, 66, 12, 87, 3, 66, 24, 87, 1, 66, 14, 87];
         assert_eq!(
-            reduce(&data),
+            reduce(&data).unwrap(),
             String::from("WWWWWWWWWWWWBWWWWWWWWWWWWBBBWWWWWWWWWWWWWWWWWWWWWWWWBWWWWWWWWWWWWWW")
                 .into_bytes()
         );
     }
+
+    #[test]
+    fn test_empty() {
+        assert!(apply(&[]).unwrap().is_empty());
+        assert!(reduce(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_reduce_truncated() {
+        assert!(reduce(&[12, 87, 1]).is_err());
+    }
 }
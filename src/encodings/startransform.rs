@@ -1,162 +1,320 @@
-use std::collections::{HashMap, HashSet};
-use std::iter::Iterator;
-use std::str::from_utf8;
+use std::collections::HashMap;
+
+use crate::codec::Codec;
+use crate::error::{ComprsError, Result};
 
 // http://www.romjist.ro/content/pdf/08-radescu.pdf
 
-const MIN_LENGH: usize = 2;
+const MIN_LENGTH: usize = 2;
 const MAX_LENGTH: usize = 22;
-const ENCODING: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVXXYZ";
-const VALID: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVXXYZ";
-const DELIMS: [char; 32] = [
-    ' ', '\t', ',', ';', '.', ':', '?', '!', '(', ')', '[', ']', '{', '}', '<', '>', '/', '\\',
-    '#', '`', '\'', '"', '|', '=', '-', '&', '_', '‘', '’', '+', '~', '@',
-];
+/// Letters of the codes besides `STAR`, words consist of the same letters
+const ENCODING: &[u8; 52] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const STAR: u8 = b'*';
+/// Precedes literal `STAR` and `ESCAPE` bytes of the input
+const ESCAPE: u8 = b'\\';
+/// Terminates each word of the dictionary
+const SEPARATOR: u8 = b'\n';
+/// Words which occur less often are kept, as storing them in the dictionary costs more
+const MIN_COUNT: usize = 2;
 
+#[derive(Serialize, Deserialize)]
 struct StarTransformData {
-    pub translation_table: HashMap<String, String>,
+    /// Replaced words in the order their codes were assigned
+    pub dictionary: Vec<u8>,
     pub content: Vec<u8>,
 }
 
-fn is_word(word: &str) -> bool {
-    let len = word.len();
-    if len < MIN_LENGH || len > MAX_LENGTH {
-        return false;
+/// Star transform, which replaces frequent words with codes of the same length made up
+/// mostly of `*`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StarTransform;
+
+impl Codec for StarTransform {
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        apply(data)
     }
-    for ch in word.chars() {
-        if !VALID.contains(ch) {
-            return false;
-        }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        reduce(data)
     }
-    true
 }
 
-pub fn apply(data: &[u8]) -> Vec<u8> {
-    // Assume UTF-8 encoding
-    let file_content = match from_utf8(&data) {
-        Ok(v) => v,
-        Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-    };
+#[inline]
+fn is_letter(byte: u8) -> bool {
+    byte.is_ascii_alphabetic()
+}
+
+/// Assigns codes in order for each word length. The first code of a length consists of
+/// stars only, later ones replace more and more stars with letters.
+#[derive(Default)]
+struct Codes {
+    next: [u64; MAX_LENGTH + 1],
+}
+
+impl Codes {
+    /// Returns the next code of the given length, `None` if all are used
+    fn next(&mut self, len: usize) -> Option<Vec<u8>> {
+        let base = ENCODING.len() as u64 + 1;
+        loop {
+            let mut index = self.next[len];
+            if base.checked_pow(len as u32).is_some_and(|end| index >= end) {
+                return None;
+            }
+            self.next[len] += 1;
 
-    let mut dictionaries = vec![HashMap::new(); MAX_LENGTH + 1];
-    for line in file_content.lines() {
-        for word in line.split(&DELIMS[..]) {
-            if is_word(word) {
-                // Populate word occurrences in sub dictionary
-                *dictionaries[word.len()]
-                    .entry(String::from(word))
-                    .or_insert(0) += 1;
+            // Digits of the index in base 53 with zero as star, least significant last
+            let mut code = vec![STAR; len];
+            for byte in code.iter_mut().rev() {
+                let digit = (index % base) as usize;
+                index /= base;
+                if digit > 0 {
+                    *byte = ENCODING[digit - 1];
+                }
+            }
+            // Codes without a star could not be told apart from words
+            if code.contains(&STAR) {
+                return Some(code);
             }
         }
     }
+}
 
-    // Create translation table
-    let mut translation_table = HashMap::new();
+pub fn apply(data: &[u8]) -> Result<Vec<u8>> {
+    let mut counts: HashMap<&[u8], usize> = HashMap::new();
+    for word in data.split(|b| !is_letter(*b)) {
+        if (MIN_LENGTH..=MAX_LENGTH).contains(&word.len()) {
+            *counts.entry(word).or_insert(0) += 1;
+        }
+    }
 
-    for (i, item) in dictionaries.into_iter().enumerate().skip(1) {
-        let mut dict: Vec<(&String, &usize)> = item.iter().collect();
-        dict.sort_by(|a, b| b.1.cmp(a.1));
+    // Sort by length and then descending frequency, so frequent words get the most stars
+    let mut words: Vec<(&[u8], usize)> = counts
+        .into_iter()
+        .filter(|(_, count)| *count >= MIN_COUNT)
+        .collect();
+    words.sort_by(|a, b| {
+        a.0.len()
+            .cmp(&b.0.len())
+            .then(b.1.cmp(&a.1))
+            .then(a.0.cmp(b.0))
+    });
 
-        // Ignore empty dictionaries
-        if !dict.is_empty() {
-            translation_table.extend(encode_dictionary(dict, i));
+    let mut codes = Codes::default();
+    let mut translation_table = HashMap::with_capacity(words.len());
+    let mut dictionary = Vec::new();
+    for (word, _) in words {
+        if let Some(code) = codes.next(word.len()) {
+            translation_table.insert(word, code);
+            dictionary.extend_from_slice(word);
+            dictionary.push(SEPARATOR);
         }
     }
+    debug!(
+        "DEBUG:ST: {} words in the dictionary",
+        translation_table.len()
+    );
 
-    // Verify translation table only holds unique values
-    debug_assert!({
-        let mut value_set = HashSet::new();
-        for value in translation_table.values() {
-            value_set.insert(value);
+    // Translate whole runs of letters, everything else is copied with stars escaped
+    let mut content = Vec::with_capacity(data.len());
+    let mut position = 0;
+    while position < data.len() {
+        let byte = data[position];
+        if is_letter(byte) {
+            let len = data[position..]
+                .iter()
+                .take_while(|b| is_letter(**b))
+                .count();
+            let word = &data[position..position + len];
+            match translation_table.get(word) {
+                Some(code) => content.extend_from_slice(code),
+                None => content.extend_from_slice(word),
+            }
+            position += len;
+            continue;
         }
-        true
-    });
+        if byte == STAR || byte == ESCAPE {
+            content.push(ESCAPE);
+        }
+        content.push(byte);
+        position += 1;
+    }
+
+    Ok(bincode::serialize(&StarTransformData {
+        dictionary,
+        content,
+    })?)
+}
 
-    // Translate content with table
-    let final_content = translate_content(file_content, &translation_table);
-    println!("{}", &final_content);
+pub fn reduce(data: &[u8]) -> Result<Vec<u8>> {
+    let data: StarTransformData = bincode::deserialize(data)?;
 
-    // Prepare serialization data
-    let data = StarTransformData {
-        translation_table,
-        content: Vec::from(final_content.as_bytes()),
+    let mut codes = Codes::default();
+    let mut translation_table = HashMap::new();
+    let words = match data.dictionary.split_last() {
+        Some((&SEPARATOR, words)) => words,
+        Some(_) => return Err(ComprsError::corrupt("unterminated ST dictionary")),
+        None => &[],
     };
+    if !words.is_empty() {
+        for word in words.split(|b| *b == SEPARATOR) {
+            if !(MIN_LENGTH..=MAX_LENGTH).contains(&word.len())
+                || !word.iter().all(|b| is_letter(*b))
+            {
+                return Err(ComprsError::corrupt("invalid word in ST dictionary"));
+            }
+            let code = codes
+                .next(word.len())
+                .ok_or_else(|| ComprsError::corrupt("too many words in ST dictionary"))?;
+            translation_table.insert(code, word);
+        }
+    }
 
-    data.content
+    let content = data.content;
+    let mut decoded = Vec::with_capacity(content.len());
+    let mut position = 0;
+    while position < content.len() {
+        let byte = content[position];
+        if byte == ESCAPE {
+            let escaped = content
+                .get(position + 1)
+                .ok_or_else(|| ComprsError::corrupt("truncated ST escape"))?;
+            decoded.push(*escaped);
+            position += 2;
+        } else if is_letter(byte) || byte == STAR {
+            let len = content[position..]
+                .iter()
+                .take_while(|b| is_letter(**b) || **b == STAR)
+                .count();
+            let token = &content[position..position + len];
+            if token.contains(&STAR) {
+                let word = translation_table
+                    .get(token)
+                    .ok_or_else(|| ComprsError::corrupt("unknown ST code"))?;
+                decoded.extend_from_slice(word);
+            } else {
+                decoded.extend_from_slice(token);
+            }
+            position += len;
+        } else {
+            decoded.push(byte);
+            position += 1;
+        }
+    }
+    Ok(decoded)
 }
 
-/// Takes a dictionary vector as input (sorted by word occurrence)
-fn encode_dictionary(dict: Vec<(&String, &usize)>, len: usize) -> HashMap<String, String> {
-    let mut translation_table: HashMap<String, String> = HashMap::with_capacity(dict.len());
+#[cfg(test)]
+mod tests {
+    use super::{apply, reduce, Codes, StarTransformData, ENCODING, MAX_LENGTH};
 
-    // Create word iterator
-    let mut words = dict.into_iter().map(|(k, _)| k.clone());
+    #[test]
+    fn test_codes() {
+        let mut codes = Codes::default();
+        assert_eq!(codes.next(3).unwrap(), b"***");
+        assert_eq!(codes.next(3).unwrap(), b"**a");
+        assert_eq!(codes.next(2).unwrap(), b"**");
 
-    // translate first word with '*' * len
-    let mut code = vec!['*'; len];
-    if let Some(word) = words.next() {
-        translation_table.insert(word, code.iter().collect());
+        // Two letter codes need a star, which leaves 1 + 2 * 52 of them
+        let mut codes = Codes::default();
+        let two: Vec<Vec<u8>> = (0..).map_while(|_| codes.next(2)).collect();
+        assert_eq!(two.len(), 105);
+        assert!(two.iter().all(|code| code.contains(&b'*')));
     }
 
-    // Iterate over each index in reverse to place single mutation
-    for i in (0..len).rev() {
-        populate_table(&mut translation_table, &mut words, &code, i);
-        code[i] = '*'
+    #[test]
+    fn test_apply() {
+        let data = b"the cat and the hat, the end".to_vec();
+        let encoded = apply(&data).unwrap();
+        let decoded: StarTransformData = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(decoded.dictionary, b"the\n");
+        assert_eq!(decoded.content, b"*** cat and *** hat, *** end");
+        assert_eq!(reduce(&encoded).unwrap(), data);
     }
 
-    for offset in 1..len {
-        for ch in ENCODING.chars() {
-            code[len - offset] = ch;
-            for i in (0..len - offset).rev() {
-                populate_table(&mut translation_table, &mut words, &code, i);
-                code[i] = '*'
-            }
+    #[test]
+    fn test_roundtrip() {
+        let inputs: Vec<Vec<u8>> = vec![
+            Vec::new(),
+            b"a".to_vec(),
+            b"stars * and **escapes\\ in *stars* and \\escapes\\".to_vec(),
+            b"**a **a ab ab \\**a".to_vec(),
+            vec![0xff, b'a', b'b', 0xc3, b'a', b'b', 0x80, b'*'],
+        ];
+        for input in &inputs {
+            assert_eq!(&reduce(&apply(input).unwrap()).unwrap(), input);
         }
     }
 
-    for (key, value) in &translation_table {
-        println!("key: {}, value: {}", key, value);
+    #[test]
+    fn test_code_exhaustion() {
+        // Distinct two letter words, more than there are two letter codes
+        let words: Vec<Vec<u8>> = (0..120)
+            .map(|i| vec![ENCODING[i / 52], ENCODING[i % 52]])
+            .collect();
+        let text = words.join(&b' ');
+        let data = [&text[..], &text[..]].join(&b' ');
+        let encoded = apply(&data).unwrap();
+        let decoded: StarTransformData = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(decoded.dictionary.len(), 105 * 3);
+        assert!(decoded.dictionary.starts_with(b"aA\naB\n"));
+        // The words sorted last keep their letters
+        assert!(decoded
+            .content
+            .ends_with(b" cb cc cd ce cf cg ch ci cj ck cl cm cn co cp"));
+        assert!(!decoded.content.windows(4).any(|w| w == b" ca "));
+        assert_eq!(reduce(&encoded).unwrap(), data);
     }
 
-    debug_assert!(&words.next().is_none());
-
-    translation_table
-}
+    #[test]
+    fn test_word_boundaries() {
+        // Bytes outside ASCII end words, also within UTF-8 sequences
+        let data = "the\u{e9}the the\u{e9}t \u{fc}the".as_bytes().to_vec();
+        let encoded = apply(&data).unwrap();
+        let decoded: StarTransformData = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(decoded.dictionary, b"the\n");
+        assert_eq!(
+            decoded.content,
+            "***\u{e9}*** ***\u{e9}t \u{fc}***".as_bytes()
+        );
+        assert_eq!(reduce(&encoded).unwrap(), data);
 
-fn populate_table<I: Iterator>(
-    translation_table: &mut HashMap<String, String>,
-    words: &mut I,
-    code: &[char],
-    index: usize,
-) where
-    I: Iterator<Item = String>,
-{
-    let mut code = code.to_owned();
-    for character in ENCODING.chars() {
-        code[index] = character;
-        if let Some(word) = words.next() {
-            translation_table.insert(word.clone(), code.iter().collect());
-        } else {
-            break;
-        }
+        // Words longer than `MAX_LENGTH` are kept
+        let longest = "x".repeat(MAX_LENGTH);
+        let longer = "y".repeat(MAX_LENGTH + 1);
+        let data = format!("{0} {1} {0} {1}", longest, longer).into_bytes();
+        let encoded = apply(&data).unwrap();
+        let decoded: StarTransformData = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(decoded.dictionary, format!("{}\n", longest).as_bytes());
+        let code = "*".repeat(MAX_LENGTH);
+        assert_eq!(
+            decoded.content,
+            format!("{0} {1} {0} {1}", code, longer).as_bytes()
+        );
+        assert_eq!(reduce(&encoded).unwrap(), data);
     }
-}
 
-// TODO: replace with ropey
-fn translate_content(content: &str, translation_table: &HashMap<String, String>) -> String {
-    let mut final_lines: Vec<String> = Vec::new();
-    for line in content.lines().collect::<Vec<&str>>() {
-        let mut final_line: String = String::from(line);
-        for word in line.split(&DELIMS[..]) {
-            if is_word(word) {
-                // Replace word in final line
-                println!("replacing: {}", String::from(word));
-                final_line =
-                    final_line.replace(word, &translation_table.get(&String::from(word)).unwrap());
-            }
+    #[test]
+    fn test_corrupt() {
+        let invalid = vec![
+            StarTransformData {
+                dictionary: b"the".to_vec(),
+                content: b"***".to_vec(),
+            },
+            StarTransformData {
+                dictionary: b"th3\n".to_vec(),
+                content: b"***".to_vec(),
+            },
+            StarTransformData {
+                dictionary: b"the\n".to_vec(),
+                content: b"**a".to_vec(),
+            },
+            StarTransformData {
+                dictionary: Vec::new(),
+                content: b"end\\".to_vec(),
+            },
+        ];
+        for data in &invalid {
+            assert!(reduce(&bincode::serialize(data).unwrap()).is_err());
         }
-        final_lines.push(final_line);
     }
-    //TODO: restore original line seperator
-    final_lines.join("\n")
 }
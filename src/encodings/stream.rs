@@ -0,0 +1,353 @@
+use std::cmp;
+use std::io::{self, Read, Write};
+
+use adler32::RollingAdler32;
+
+use crate::codec::Codec;
+use crate::error::{ComprsError, Result};
+
+use super::header::{read_summary, write_summary, Header};
+use super::{decode_pipeline, encode_pipeline, Preset, TData};
+
+/// Minimum amount of uncompressed input processed as one block
+pub const BLOCK_SIZE: usize = 4 * 1024 * 1024;
+
+/// The complete container format as a `Codec`, streaming variants work block by block
+#[derive(Clone, Debug, Default)]
+pub struct Comprs {
+    pub preset: Preset,
+}
+
+impl Codec for Comprs {
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        encode_pipeline(data, self.preset.clone())
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        decode_pipeline(data)
+    }
+
+    fn encode_stream(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<u64> {
+        let mut encoder =
+            ComprsEncoder::with_preset(CountingWriter::new(writer), self.preset.clone());
+        io::copy(reader, &mut encoder)?;
+        Ok(encoder.finish()?.count)
+    }
+
+    fn decode_stream(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<u64> {
+        let restored = io::copy(&mut ComprsDecoder::new(reader), writer)?;
+        writer.flush()?;
+        Ok(restored)
+    }
+}
+
+/// Counts the bytes passed to the inner writer
+struct CountingWriter<W: Write> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> CountingWriter<W> {
+    fn new(inner: W) -> Self {
+        CountingWriter { inner, count: 0 }
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.count += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Compresses everything written to it in blocks of bounded size.
+///
+/// Each block is passed through the transform pipeline on its own and written as
+/// a length-prefixed record, a zero length marks the end of the stream.
+/// If the whole input fits into one block, its length and checksum are stored in the header,
+/// otherwise they are appended after the end of the stream.
+pub struct ComprsEncoder<W: Write> {
+    writer: W,
+    buffer: Vec<u8>,
+    block_size: usize,
+    preset: Preset,
+    header_written: bool,
+    checksum: RollingAdler32,
+    total_len: u64,
+}
+
+impl<W: Write> ComprsEncoder<W> {
+    pub fn new(writer: W) -> Self {
+        ComprsEncoder::with_preset(writer, Preset::default())
+    }
+
+    /// Creates an encoder whose blocks are large enough to hold a BWT chunk of the preset
+    pub fn with_preset(writer: W, preset: Preset) -> Self {
+        let block_size = cmp::max(BLOCK_SIZE, preset.bwt_chunk_size);
+        ComprsEncoder::with_block_size(writer, preset, block_size)
+    }
+
+    pub fn with_block_size(writer: W, preset: Preset, block_size: usize) -> Self {
+        debug_assert!(block_size > 0);
+        ComprsEncoder {
+            writer,
+            buffer: Vec::with_capacity(block_size),
+            block_size,
+            preset,
+            header_written: false,
+            checksum: RollingAdler32::new(),
+            total_len: 0,
+        }
+    }
+
+    /// Encodes remaining buffered data, terminates the stream and returns the inner writer.
+    /// It is important that this method is called at the end of each encoding process.
+    pub fn finish(mut self) -> Result<W> {
+        let streamed = self.header_written;
+        if !streamed {
+            Header::new(self.total_len, self.checksum.hash()).write(&mut self.writer)?;
+            self.header_written = true;
+        }
+
+        self.write_block()?;
+        self.writer.write_all(&0u32.to_le_bytes())?;
+        if streamed {
+            write_summary(&mut self.writer, self.total_len, self.checksum.hash())?;
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_block(&mut self) -> Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        if !self.header_written {
+            Header::streamed().write(&mut self.writer)?;
+            self.header_written = true;
+        }
+
+        let data = TData::encode(&self.buffer, &self.preset)?;
+        let block = bincode::serialize(&data)?;
+        debug!(
+            "DEBUG: encoded block of {} bytes into {} bytes",
+            self.buffer.len(),
+            block.len()
+        );
+
+        self.writer.write_all(&(block.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&block)?;
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for ComprsEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = cmp::min(self.block_size - self.buffer.len(), buf.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+        self.checksum.update_buffer(&buf[..len]);
+        self.total_len += len as u64;
+        if self.buffer.len() == self.block_size {
+            self.write_block()?;
+        }
+        Ok(len)
+    }
+
+    /// Flushes the inner writer, partially filled blocks are kept until they are full or `finish` is called.
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Decompresses a stream produced by `ComprsEncoder` one block at a time.
+/// Length and checksum of the restored data are verified once the end of the stream is reached.
+pub struct ComprsDecoder<R: Read> {
+    reader: R,
+    header: Option<Header>,
+    block: Vec<u8>,
+    position: usize,
+    eof: bool,
+    checksum: RollingAdler32,
+    total_len: u64,
+}
+
+impl<R: Read> ComprsDecoder<R> {
+    pub fn new(reader: R) -> Self {
+        ComprsDecoder {
+            reader,
+            header: None,
+            block: Vec::new(),
+            position: 0,
+            eof: false,
+            checksum: RollingAdler32::new(),
+            total_len: 0,
+        }
+    }
+
+    /// Reads and decodes the next block, returns false once the end of the stream is reached
+    fn read_block(&mut self) -> Result<bool> {
+        if self.header.is_none() {
+            self.header = Some(Header::read(&mut self.reader)?);
+        }
+
+        let mut len = [0u8; 4];
+        self.reader.read_exact(&mut len)?;
+        let len = u32::from_le_bytes(len) as usize;
+        if len == 0 {
+            self.verify()?;
+            return Ok(false);
+        }
+
+        let mut block = Vec::new();
+        self.reader
+            .by_ref()
+            .take(len as u64)
+            .read_to_end(&mut block)?;
+        if block.len() != len {
+            return Err(ComprsError::corrupt("truncated block"));
+        }
+
+        let data: TData = bincode::deserialize(&block)?;
+        self.block = data.decode()?;
+        self.position = 0;
+        self.checksum.update_buffer(&self.block);
+        self.total_len += self.block.len() as u64;
+        Ok(true)
+    }
+
+    fn verify(&mut self) -> Result<()> {
+        let header = self.header.as_mut().unwrap();
+        if header.is_streamed() {
+            let (original_len, checksum) = read_summary(&mut self.reader)?;
+            header.original_len = original_len;
+            header.checksum = checksum;
+        }
+        header.verify(self.total_len, self.checksum.hash())
+    }
+}
+
+impl<R: Read> Read for ComprsDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.block.len() {
+            if self.eof || !self.read_block()? {
+                self.eof = true;
+                return Ok(0);
+            }
+        }
+
+        let len = cmp::min(self.block.len() - self.position, buf.len());
+        buf[..len].copy_from_slice(&self.block[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use crate::error::{ComprsError, Result};
+
+    use super::super::encode_pipeline;
+    use super::{ComprsDecoder, ComprsEncoder, Preset};
+
+    fn decode(data: &[u8]) -> Result<Vec<u8>> {
+        let mut restored = Vec::new();
+        ComprsDecoder::new(data).read_to_end(&mut restored)?;
+        Ok(restored)
+    }
+
+    #[test]
+    fn test_blocks() -> Result<()> {
+        let data: Vec<u8> = (0..10_000)
+            .map(|i| (i % 251) as u8 ^ (i / 100) as u8)
+            .collect();
+
+        for level in 0..=6 {
+            let preset = Preset::from_level(level);
+            let mut encoder = ComprsEncoder::with_block_size(Vec::new(), preset, 1024);
+            for chunk in data.chunks(700) {
+                encoder.write_all(chunk)?;
+            }
+            let compressed = encoder.finish()?;
+
+            assert_eq!(decode(&compressed)?, data);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty() -> Result<()> {
+        let compressed = ComprsEncoder::new(Vec::new()).finish()?;
+        assert!(decode(&compressed)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupt_checksum() -> Result<()> {
+        // Small inputs store the checksum in the header
+        let mut encoder = ComprsEncoder::new(Vec::new());
+        encoder.write_all(b"comprs")?;
+        let mut compressed = encoder.finish()?;
+        compressed[14] ^= 0xff;
+        match decode(&compressed) {
+            Err(ComprsError::ChecksumMismatch { .. }) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        // Larger inputs store it after the last block
+        let mut encoder = ComprsEncoder::with_block_size(Vec::new(), Preset::default(), 16);
+        encoder.write_all(&[7; 100])?;
+        let mut compressed = encoder.finish()?;
+        let len = compressed.len();
+        compressed[len - 1] ^= 0xff;
+        match decode(&compressed) {
+            Err(ComprsError::ChecksumMismatch { .. }) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupt_bytes() -> Result<()> {
+        let data =
+            String::from("If you are going to use a passage of Lorem Ipsum, you need to be sure")
+                .into_bytes();
+        for level in &[0, 3] {
+            let compressed = encode_pipeline(data.as_slice(), Preset::from_level(*level))?;
+            for i in 0..compressed.len() {
+                let mut corrupted = compressed.clone();
+                corrupted[i] ^= 0x55;
+                assert!(decode(&corrupted).is_err());
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_magic() {
+        match decode(b"PK\x03\x04") {
+            Err(ComprsError::Corrupt(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_truncated() -> Result<()> {
+        let data = vec![42; 5000];
+        let mut encoder = ComprsEncoder::new(Vec::new());
+        encoder.write_all(&data)?;
+        let compressed = encoder.finish()?;
+
+        for len in 0..compressed.len() {
+            assert!(decode(&compressed[..len]).is_err());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+use crate::codec::Codec;
+use crate::error::{ComprsError, Result};
+
+/// Precedes a lower cased word whose first letter was upper case
+const CAPITAL: u8 = 0x01;
+/// Precedes a lower cased word which was all upper case
+const UPPER: u8 = 0x02;
+/// Precedes literal flag bytes of the input, and line feeds without a carriage return
+/// if line endings are converted
+const ESCAPE: u8 = 0x03;
+
+#[derive(Serialize, Deserialize)]
+struct TextData {
+    /// Line endings of the input were CRLF and are stored as LF
+    pub crlf: bool,
+    pub content: Vec<u8>,
+}
+
+/// Reversible text filter which moves the case of words into flags and stores
+/// CRLF line endings as LF, so that case and line ending variants share contexts
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TextFilter;
+
+impl Codec for TextFilter {
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        apply(data)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        reduce(data)
+    }
+}
+
+pub fn apply(data: &[u8]) -> Result<Vec<u8>> {
+    // Convert line endings if most line feeds follow a carriage return
+    let line_feeds = data.iter().filter(|b| **b == b'\n').count();
+    let crlf_count = data.windows(2).filter(|pair| pair == b"\r\n").count();
+    let crlf = crlf_count * 2 > line_feeds;
+
+    let mut content = Vec::with_capacity(data.len() + data.len() / 16);
+    let mut position = 0;
+    while position < data.len() {
+        let byte = data[position];
+        if byte.is_ascii_alphabetic() {
+            let len = data[position..]
+                .iter()
+                .take_while(|b| b.is_ascii_alphabetic())
+                .count();
+            let word = &data[position..position + len];
+            let (first, rest) = (word[0], &word[1..]);
+            let flag = if first.is_ascii_uppercase() && rest.iter().all(u8::is_ascii_lowercase) {
+                Some(CAPITAL)
+            } else if len > 1 && word.iter().all(u8::is_ascii_uppercase) {
+                Some(UPPER)
+            } else {
+                None
+            };
+            match flag {
+                Some(flag) => {
+                    content.push(flag);
+                    content.extend(word.iter().map(u8::to_ascii_lowercase));
+                }
+                None => content.extend_from_slice(word),
+            }
+            position += len;
+            continue;
+        }
+
+        if crlf && byte == b'\r' && data.get(position + 1) == Some(&b'\n') {
+            content.push(b'\n');
+            position += 2;
+            continue;
+        }
+        if byte == CAPITAL || byte == UPPER || byte == ESCAPE || (crlf && byte == b'\n') {
+            content.push(ESCAPE);
+        }
+        content.push(byte);
+        position += 1;
+    }
+    debug!("DEBUG:TEXT: converted line endings: {}", crlf);
+
+    Ok(bincode::serialize(&TextData { crlf, content })?)
+}
+
+pub fn reduce(data: &[u8]) -> Result<Vec<u8>> {
+    let TextData { crlf, content } = bincode::deserialize(data)?;
+
+    let mut decoded = Vec::with_capacity(content.len() + content.len() / 16);
+    let mut position = 0;
+    while position < content.len() {
+        let byte = content[position];
+        position += 1;
+        match byte {
+            CAPITAL | UPPER => {
+                let len = content[position..]
+                    .iter()
+                    .take_while(|b| b.is_ascii_lowercase())
+                    .count();
+                if len == 0 {
+                    return Err(ComprsError::corrupt("text case flag without word"));
+                }
+                let start = decoded.len();
+                decoded.extend_from_slice(&content[position..position + len]);
+                if byte == CAPITAL {
+                    decoded[start].make_ascii_uppercase();
+                } else {
+                    decoded[start..].make_ascii_uppercase();
+                }
+                position += len;
+            }
+            ESCAPE => {
+                let escaped = content
+                    .get(position)
+                    .ok_or_else(|| ComprsError::corrupt("truncated text escape"))?;
+                decoded.push(*escaped);
+                position += 1;
+            }
+            b'\n' if crlf => decoded.extend_from_slice(b"\r\n"),
+            _ => decoded.push(byte),
+        }
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply, reduce, TextData};
+
+    #[test]
+    fn test_apply() {
+        let data = b"The CSV, the csv\r\nand McDonald's I\r\n".to_vec();
+        let encoded = apply(&data).unwrap();
+        let decoded: TextData = bincode::deserialize(&encoded).unwrap();
+        assert!(decoded.crlf);
+        assert_eq!(
+            decoded.content,
+            b"\x01the \x02csv, the csv\nand McDonald's \x01i\n"
+        );
+        assert_eq!(reduce(&encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn test_line_endings() {
+        // Line endings are only converted if more than half of the line feeds are CRLF
+        let half = b"a\r\nb\nc\r\nd\n".to_vec();
+        let encoded = apply(&half).unwrap();
+        let decoded: TextData = bincode::deserialize(&encoded).unwrap();
+        assert!(!decoded.crlf);
+        assert_eq!(decoded.content, half);
+        assert_eq!(reduce(&encoded).unwrap(), half);
+
+        let most = b"a\r\nb\nc\r\nd\ne\r\n".to_vec();
+        let encoded = apply(&most).unwrap();
+        let decoded: TextData = bincode::deserialize(&encoded).unwrap();
+        assert!(decoded.crlf);
+        assert_eq!(decoded.content, b"a\nb\x03\nc\nd\x03\ne\n");
+        assert_eq!(reduce(&encoded).unwrap(), most);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let inputs: Vec<Vec<u8>> = vec![
+            Vec::new(),
+            b"A".to_vec(),
+            b"unix\nlines\n".to_vec(),
+            b"mostly\r\nwindows\r\nlines\nwith\r\r\na lone\n\rfeed\r\n".to_vec(),
+            b"flag \x01 bytes \x02\x03 in\r\n \x01The\x03 input\r\n".to_vec(),
+            (0..=255).collect(),
+        ];
+        for input in &inputs {
+            assert_eq!(&reduce(&apply(input).unwrap()).unwrap(), input);
+        }
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let invalid = vec![
+            TextData {
+                crlf: false,
+                content: b"\x01The".to_vec(),
+            },
+            TextData {
+                crlf: false,
+                content: b"end\x02".to_vec(),
+            },
+            TextData {
+                crlf: true,
+                content: b"end\x03".to_vec(),
+            },
+        ];
+        for data in &invalid {
+            assert!(reduce(&bincode::serialize(data).unwrap()).is_err());
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+pub type Result<T> = std::result::Result<T, ComprsError>;
+
+/// Errors returned by all encode and decode functions
+#[derive(Debug)]
+pub enum ComprsError {
+    /// Reading or writing the underlying stream failed
+    Io(io::Error),
+    /// The compressed data is malformed or truncated
+    Corrupt(String),
+    /// The stream was written with a format version this build cannot read
+    UnsupportedVersion(u8),
+    /// The restored data does not match the stored checksum
+    ChecksumMismatch { expected: u32, actual: u32 },
+    /// The transform pipeline is unknown or cannot be decoded
+    InvalidTransform(String),
+}
+
+impl ComprsError {
+    pub fn corrupt<S: Into<String>>(message: S) -> Self {
+        ComprsError::Corrupt(message.into())
+    }
+}
+
+impl fmt::Display for ComprsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ComprsError::Io(err) => write!(f, "{}", err),
+            ComprsError::Corrupt(message) => write!(f, "corrupt data: {}", message),
+            ComprsError::UnsupportedVersion(version) => {
+                write!(f, "unsupported format version {}", version)
+            }
+            ComprsError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "checksum does not match: expected {}, got {}",
+                expected, actual
+            ),
+            ComprsError::InvalidTransform(message) => write!(f, "invalid transform: {}", message),
+        }
+    }
+}
+
+impl error::Error for ComprsError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ComprsError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ComprsError {
+    /// Unwraps errors which were passed through a `Read` or `Write` implementation
+    fn from(err: io::Error) -> Self {
+        if matches!(err.get_ref(), Some(e) if e.is::<ComprsError>()) {
+            return *err.into_inner().unwrap().downcast::<ComprsError>().unwrap();
+        }
+        ComprsError::Io(err)
+    }
+}
+
+impl From<bincode::Error> for ComprsError {
+    fn from(err: bincode::Error) -> Self {
+        match *err {
+            bincode::ErrorKind::Io(err) => ComprsError::from(err),
+            err => ComprsError::Corrupt(err.to_string()),
+        }
+    }
+}
+
+impl From<ComprsError> for io::Error {
+    fn from(err: ComprsError) -> Self {
+        match err {
+            ComprsError::Io(err) => err,
+            err => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::ComprsError;
+
+    #[test]
+    fn test_io_roundtrip() {
+        let err: io::Error = ComprsError::UnsupportedVersion(42).into();
+        match ComprsError::from(err) {
+            ComprsError::UnsupportedVersion(42) => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        let err = io::Error::new(io::ErrorKind::UnexpectedEof, "eof");
+        match ComprsError::from(err) {
+            ComprsError::Io(e) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+}
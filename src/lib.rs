@@ -0,0 +1,28 @@
+//! Experimental playground for compression algorithms.
+//!
+//! Every encoding in `encodings` implements the `Codec` trait and can be used on its own,
+//! `ComprsEncoder` and `ComprsDecoder` combine them into the streaming `.comprs` format.
+
+extern crate adler32;
+extern crate bincode;
+extern crate bitbit;
+#[macro_use]
+extern crate log;
+extern crate rayon;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate varuint;
+
+pub use crate::codec::Codec;
+pub use crate::encodings::stream::Comprs;
+pub use crate::encodings::{
+    decode_pipeline, encode_pipeline, parse_pipeline, ComprsDecoder, ComprsEncoder, Preset,
+    Transform,
+};
+pub use crate::error::{ComprsError, Result};
+
+pub mod codec;
+pub mod encodings;
+pub mod error;
+pub mod utils;
@@ -1,29 +1,37 @@
 extern crate adler32;
-extern crate bincode;
-extern crate bitbit;
 #[macro_use]
 extern crate clap;
+extern crate comprs;
 #[macro_use]
 extern crate log;
-extern crate rayon;
-extern crate serde;
-#[macro_use]
-extern crate serde_derive;
-extern crate varuint;
 
 use std::fs::File;
-use std::io::{BufReader, BufWriter, Cursor, Read, Result, Write};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::process;
 use std::time::Instant;
 
-use adler32::adler32;
+use adler32::{adler32, RollingAdler32};
 use clap::{App, Arg};
 
-use utils::print_statistics;
+use comprs::codec::Codec;
+use comprs::encodings::arithmetic_coder::Backend;
+use comprs::encodings::bzip2::Bzip2;
+use comprs::encodings::deflate::gzip::Gzip;
+use comprs::encodings::deflate::Deflate;
+use comprs::encodings::lipt::Dictionary;
+use comprs::encodings::ppm::EscapeMethod;
+use comprs::encodings::rans::Mode as RansMode;
+use comprs::utils::print_statistics;
+use comprs::{ComprsDecoder, ComprsEncoder, ComprsError, Preset, Result};
 
-mod encodings;
-mod utils;
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
 
-fn main() -> Result<()> {
+fn run() -> Result<()> {
     let matches = App::new("comprs")
         .version(crate_version!())
         .about("Experimental playground for compression algorithms in Rust")
@@ -48,6 +56,98 @@ fn main() -> Result<()> {
                 .possible_values(&["0", "1", "2", "3", "4", "5", "6"])
                 .help("Specify compression level"),
         )
+        .arg(
+            Arg::with_name("pipeline")
+                .long("pipeline")
+                .takes_value(true)
+                .value_name("transforms")
+                .help("Comma separated list of transforms to apply, e.g. bwt,mtf,rle,ppm"),
+        )
+        .arg(
+            Arg::with_name("escape")
+                .long("escape")
+                .takes_value(true)
+                .value_name("method")
+                .possible_values(&["a", "b", "c", "d"])
+                .help("Escape estimation method of the PPM stage"),
+        )
+        .arg(
+            Arg::with_name("coder")
+                .long("coder")
+                .takes_value(true)
+                .value_name("coder")
+                .possible_values(&["arithmetic", "range"])
+                .help("Entropy coder of the PPM stage"),
+        )
+        .arg(
+            Arg::with_name("rans")
+                .long("rans")
+                .takes_value(true)
+                .value_name("mode")
+                .possible_values(&["static", "adaptive"])
+                .help("Frequency mode of the rANS stage"),
+        )
+        .arg(
+            Arg::with_name("dictionary")
+                .long("dictionary")
+                .takes_value(true)
+                .value_name("dictionary")
+                .possible_values(&["english", "file"])
+                .help("Dictionary of the LIPT stage"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .value_name("format")
+                .possible_values(&["comprs", "gzip", "bzip2"])
+                .default_value("comprs")
+                .help("File format to write or read"),
+        )
+        .arg(
+            Arg::with_name("window")
+                .long("window")
+                .takes_value(true)
+                .value_name("bits")
+                .help("Window size of the LZ77 stage as a power of two"),
+        )
+        .arg(
+            Arg::with_name("threshold")
+                .long("threshold")
+                .takes_value(true)
+                .value_name("position")
+                .help("Position which new bytes of the sticky MTF stage move to"),
+        )
+        .arg(
+            Arg::with_name("wfc-window")
+                .long("wfc-window")
+                .takes_value(true)
+                .value_name("bytes")
+                .help("Number of recent bytes counted by the WFC stage"),
+        )
+        .arg(
+            Arg::with_name("order")
+                .long("order")
+                .takes_value(true)
+                .value_name("order")
+                .help("Context order of the PPM stage, * for unbounded contexts"),
+        )
+        .arg(
+            Arg::with_name("rescale-bits")
+                .long("rescale-bits")
+                .takes_value(true)
+                .value_name("bits")
+                .help(
+                    "Frequency total at which the PPM stage rescales a context, as a power of two",
+                ),
+        )
+        .arg(
+            Arg::with_name("rescale-shift")
+                .long("rescale-shift")
+                .takes_value(true)
+                .value_name("bits")
+                .help("Divisor of rescaled PPM frequencies as a power of two, 1 halves them"),
+        )
         .arg(
             Arg::with_name("v")
                 .short("v")
@@ -63,6 +163,61 @@ fn main() -> Result<()> {
         .get_matches();
 
     let input_file = String::from(matches.value_of("file").unwrap());
+    let level = value_t!(matches, "o", u8).unwrap_or_else(|e| e.exit());
+    let mut preset = Preset::from_level(level);
+    if let Some(pipeline) = matches.value_of("pipeline") {
+        preset = comprs::parse_pipeline(pipeline)
+            .map(|transforms| preset.with_transforms(transforms))
+            .unwrap_or_else(|e| {
+                clap::Error::with_description(&e.to_string(), clap::ErrorKind::InvalidValue).exit()
+            });
+    }
+    if matches.is_present("escape") {
+        preset.ppm.escape_method =
+            value_t!(matches, "escape", EscapeMethod).unwrap_or_else(|e| e.exit());
+    }
+    if matches.is_present("coder") {
+        preset.ppm.coder = value_t!(matches, "coder", Backend).unwrap_or_else(|e| e.exit());
+    }
+    if matches.is_present("rans") {
+        preset.rans = value_t!(matches, "rans", RansMode).unwrap_or_else(|e| e.exit());
+    }
+    if matches.is_present("dictionary") {
+        preset.lipt = value_t!(matches, "dictionary", Dictionary).unwrap_or_else(|e| e.exit());
+    }
+    if matches.is_present("window") {
+        preset.lz77.window_bits = value_t!(matches, "window", u8).unwrap_or_else(|e| e.exit());
+    }
+    if matches.is_present("threshold") {
+        preset.mtf.threshold = value_t!(matches, "threshold", u8).unwrap_or_else(|e| e.exit());
+    }
+    if matches.is_present("wfc-window") {
+        preset.mtf.window = value_t!(matches, "wfc-window", u16).unwrap_or_else(|e| e.exit());
+    }
+    match matches.value_of("order") {
+        Some("*") => preset.ppm.star = true,
+        Some(_) => preset.ppm.order = value_t!(matches, "order", u8).unwrap_or_else(|e| e.exit()),
+        None => {}
+    }
+    if matches.is_present("rescale-bits") {
+        preset.ppm.rescale_bits =
+            value_t!(matches, "rescale-bits", u8).unwrap_or_else(|e| e.exit());
+    }
+    if matches.is_present("rescale-shift") {
+        preset.ppm.rescale_shift =
+            value_t!(matches, "rescale-shift", u8).unwrap_or_else(|e| e.exit());
+    }
+    // Other file formats are written by a codec, with the file extension of the format
+    let format: Option<(Box<dyn Codec>, &str)> = match matches.value_of("format") {
+        Some("gzip") => Some((
+            Box::new(Gzip {
+                deflate: Deflate::from_level(level),
+            }),
+            "gz",
+        )),
+        Some("bzip2") => Some((Box::new(Bzip2::from_level(level)), "bz2")),
+        _ => None,
+    };
     let mut verify = true;
     if matches.is_present("no-verify") {
         verify = false;
@@ -71,11 +226,15 @@ fn main() -> Result<()> {
     match matches.value_of("mode").unwrap() {
         "c" | "compress" => {
             let mut reader = BufReader::new(File::open(&input_file)?);
-            let output_file = format!("{}.comprs", input_file.clone());
+            let extension = format.as_ref().map_or("comprs", |(_, extension)| extension);
+            let output_file = format!("{}.{}", input_file, extension);
             debug!("DEBUG: Saving output to: {}", &output_file);
-            let mut writer = BufWriter::new(File::create(&output_file)?);
+            let writer = BufWriter::new(File::create(&output_file)?);
 
-            writer.write_all(&compress_file(&mut reader)?)?;
+            match &format {
+                Some((codec, _)) => compress_with(&mut reader, writer, codec.as_ref())?,
+                None => compress_file(&mut reader, writer, preset)?,
+            };
 
             print_statistics(
                 &File::open(&input_file)?.metadata()?,
@@ -88,57 +247,109 @@ fn main() -> Result<()> {
 
             println!("Verifying compressed file ...");
 
-            let restored = decompress_file(&mut BufReader::new(File::open(&output_file)?))?;
-
             // Calculate checksums
             let input_checksum = adler32(&mut File::open(&input_file)?)?;
-            let restored_checksum = adler32(restored.as_slice())?;
+            let restored_checksum = match &format {
+                Some((codec, _)) => {
+                    let mut restored = ChecksumWriter(RollingAdler32::new());
+                    let mut reader = BufReader::new(File::open(&output_file)?);
+                    codec.decode_stream(&mut reader, &mut restored)?;
+                    restored.0.hash()
+                }
+                None => adler32(ComprsDecoder::new(BufReader::new(File::open(
+                    &output_file,
+                )?)))?,
+            };
 
             // Sanity check
-            if input_checksum == restored_checksum {
-                println!("checksum is OK - {}", restored_checksum);
-            } else {
-                panic!(format!(
-                    "FATAL: checksum does not match! - {}",
-                    restored_checksum
-                ));
+            if input_checksum != restored_checksum {
+                return Err(ComprsError::ChecksumMismatch {
+                    expected: input_checksum,
+                    actual: restored_checksum,
+                });
             }
+            println!("checksum is OK - {}", restored_checksum);
         }
         "d" | "decompress" => {
-            let output_file = input_file.clone().replace(".comprs", ".restored");
+            let output_file = match &format {
+                Some((_, extension)) => input_file
+                    .strip_suffix(&format!(".{}", extension))
+                    .map(String::from)
+                    .unwrap_or_else(|| format!("{}.restored", input_file)),
+                None => input_file.clone().replace(".comprs", ".restored"),
+            };
             let mut reader = BufReader::new(File::open(input_file)?);
             let mut writer = BufWriter::new(File::create(&output_file)?);
-            writer.write_all(&decompress_file(&mut reader)?)?;
+            match &format {
+                Some((codec, _)) => decompress_with(&mut reader, &mut writer, codec.as_ref())?,
+                None => decompress_file(&mut reader, &mut writer)?,
+            };
         }
         _ => unreachable!(),
     }
     Ok(())
 }
 
-fn compress_file<R: Read>(reader: R) -> Result<Vec<u8>> {
+fn compress_file<R: Read, W: Write>(mut reader: R, writer: W, preset: Preset) -> Result<W> {
     println!("Compressing file ...");
     let now = Instant::now();
-    let cursor = Cursor::new(encodings::encode_pipeline(reader)?);
-    let elapsed = now.elapsed();
-    println!(
-        "elapsed time: {}.{} seconds",
-        elapsed.as_secs(),
-        elapsed.subsec_millis()
-    );
-    Ok(cursor.into_inner())
+    let mut encoder = ComprsEncoder::with_preset(writer, preset);
+    io::copy(&mut reader, &mut encoder)?;
+    let writer = encoder.finish()?;
+    print_elapsed(now);
+    Ok(writer)
 }
 
-fn decompress_file<R: Read>(reader: R) -> Result<Vec<u8>> {
+fn compress_with<R: Read, W: Write>(mut reader: R, mut writer: W, codec: &dyn Codec) -> Result<W> {
+    println!("Compressing file ...");
+    let now = Instant::now();
+    codec.encode_stream(&mut reader, &mut writer)?;
+    print_elapsed(now);
+    Ok(writer)
+}
+
+fn decompress_file<R: Read, W: Write>(reader: R, mut writer: W) -> Result<u64> {
+    println!("Decompressing file ...");
+    let now = Instant::now();
+    let restored = io::copy(&mut ComprsDecoder::new(reader), &mut writer)?;
+    writer.flush()?;
+    print_elapsed(now);
+    Ok(restored)
+}
+
+fn decompress_with<R: Read, W: Write>(
+    mut reader: R,
+    mut writer: W,
+    codec: &dyn Codec,
+) -> Result<u64> {
     println!("Decompressing file ...");
     let now = Instant::now();
-    let result = encodings::decode_pipeline(reader);
-    let elapsed = now.elapsed();
+    let restored = codec.decode_stream(&mut reader, &mut writer)?;
+    print_elapsed(now);
+    Ok(restored)
+}
+
+/// Computes the Adler-32 checksum of everything written to it
+struct ChecksumWriter(RollingAdler32);
+
+impl Write for ChecksumWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.update_buffer(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn print_elapsed(start: Instant) {
+    let elapsed = start.elapsed();
     println!(
         "elapsed time: {}.{} seconds",
         elapsed.as_secs(),
         elapsed.subsec_millis()
     );
-    result
 }
 
 #[cfg(test)]
@@ -182,10 +393,20 @@ mod tests {
             therefore always free from repetition, injected humour, or non-characteristic words etc."
         ).into_bytes();
 
-        let compressed = compress_file(test_data.as_slice())?;
-        let restored = decompress_file(compressed.as_slice())?;
+        let compressed = compress_file(test_data.as_slice(), Vec::new(), Preset::default())?;
+        let mut restored = Vec::new();
+        decompress_file(compressed.as_slice(), &mut restored)?;
 
         assert_eq!(restored, test_data);
+
+        let codecs: Vec<Box<dyn Codec>> =
+            vec![Box::new(Gzip::default()), Box::new(Bzip2::default())];
+        for codec in codecs {
+            let compressed = compress_with(test_data.as_slice(), Vec::new(), codec.as_ref())?;
+            let mut restored = Vec::new();
+            decompress_with(compressed.as_slice(), &mut restored, codec.as_ref())?;
+            assert_eq!(restored, test_data);
+        }
         Ok(())
     }
 }
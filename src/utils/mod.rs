@@ -1,5 +1,7 @@
 use std::collections::HashMap;
 use std::fs::Metadata;
+#[cfg(test)]
+use std::iter;
 
 /// Calculates shannon entropy for the given slice
 pub fn calc_entropy(data: &[u8]) -> f64 {
@@ -30,6 +32,25 @@ pub fn print_statistics(input_meta: &Metadata, compressed_meta: &Metadata) {
     println!("Bits per Byte: {:.4}", comp_size / input_size * 8.0);
 }
 
+/// Returns the states of a xorshift generator following `seed`, for reproducible test data
+#[cfg(test)]
+pub fn xorshift(seed: u32) -> impl Iterator<Item = u32> {
+    iter::successors(Some(seed), |state| {
+        let mut state = *state;
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        Some(state)
+    })
+    .skip(1)
+}
+
+/// Returns `len` bytes without any structure to compress
+#[cfg(test)]
+pub fn random_bytes(len: usize) -> Vec<u8> {
+    xorshift(1).take(len).map(|state| state as u8).collect()
+}
+
 #[cfg(test)]
 mod tests {
     use crate::utils::calc_entropy;